@@ -0,0 +1,245 @@
+use std::{
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    sync::Arc,
+    task::{self, Poll},
+    time::Duration,
+};
+
+use futures_core::Stream;
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpListener, TcpStream},
+    sync::{mpsc, Mutex, Semaphore},
+};
+use tracing::{error, info};
+
+use crate::error::{At, ConnectionError, FramingError, Stage, StageError};
+use crate::framing::{self, Decoder, MAX_BODY_SIZE};
+use crate::reply::Reply;
+use crate::subscription::Subscriber;
+use crate::{
+    channel_topic, handle_message, renew_due, Context, Error, Event, HookListener, Mode,
+    SubscribeOutcome, READ_TIMEOUT, RENEWAL_INTERVAL, WRITE_TIMEOUT,
+};
+
+/// Events received before the stream is polled again.
+const EVENTS_CAPACITY: usize = 64;
+
+/// Events received by a listener started with [`HookListener::listen_async`].
+///
+/// The listener stops when the stream is dropped.
+#[derive(Debug)]
+pub struct EventStream {
+    receiver: mpsc::Receiver<Result<Event, Error>>,
+}
+
+impl Stream for EventStream {
+    type Item = Result<Event, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+impl HookListener {
+    /// Start listening for incoming streams on the current tokio runtime.
+    ///
+    /// Works like [`HookListener::listen`], with a task per connection and
+    /// at most `workers` connections handled at once. The received messages
+    /// are handled on the blocking thread pool, as the verify hook and the
+    /// dedup and subscription storage may block.
+    ///
+    /// The listener socket is switched to non-blocking mode, so the same
+    /// `HookListener` can not be used with [`HookListener::listen`] after.
+    ///
+    /// # Errors:
+    ///
+    /// The listener can not be registered with the runtime.
+    ///
+    /// # Panics:
+    ///
+    /// Called outside of a tokio runtime.
+    pub fn listen_async(&self) -> Result<EventStream, Error> {
+        info!("Start listening.");
+
+        let listener = self.listener.try_clone()?;
+        listener.set_nonblocking(true)?;
+        let listener = TcpListener::from_std(listener)?;
+
+        let (sender, receiver) = mpsc::channel(EVENTS_CAPACITY);
+        tokio::spawn(renew(self.subscriber(), sender.clone()));
+        tokio::spawn(accept(
+            listener,
+            Arc::new(self.context()),
+            Arc::new(Semaphore::new(self.workers)),
+            sender,
+        ));
+
+        Ok(EventStream { receiver })
+    }
+
+    /// Async version of [`HookListener::subscribe`].
+    pub async fn subscribe_async(
+        &self,
+        id: impl AsRef<str>,
+        mode: Mode,
+        lease: Option<Duration>,
+    ) -> Result<SubscribeOutcome, Error> {
+        self.subscribe_topic_async(channel_topic(id.as_ref()), mode, lease)
+            .await
+    }
+
+    /// Async version of [`HookListener::subscribe_topic`].
+    ///
+    /// The request is sent from the blocking thread pool of the runtime.
+    pub async fn subscribe_topic_async(
+        &self,
+        topic_url: impl AsRef<str>,
+        mode: Mode,
+        lease: Option<Duration>,
+    ) -> Result<SubscribeOutcome, Error> {
+        let subscriber = self.subscriber();
+        let topic_url = topic_url.as_ref().to_string();
+
+        tokio::task::spawn_blocking(move || subscriber.request(&topic_url, mode, lease))
+            .await
+            .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))
+    }
+}
+
+/// Accept connections until the event stream is dropped.
+async fn accept(
+    listener: TcpListener,
+    context: Arc<Context>,
+    workers: Arc<Semaphore>,
+    sender: mpsc::Sender<Result<Event, Error>>,
+) {
+    // Locked while sending the events of a connection so they are not
+    // interleaved with the events of another connection
+    let shared = Arc::new(Mutex::new(sender.clone()));
+
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            () = sender.closed() => break,
+        };
+
+        let stream = match accepted {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                if sender.send(Err(Error::TcpError(e))).await.is_err() {
+                    break;
+                }
+                continue;
+            }
+        };
+
+        let Ok(permit) = Arc::clone(&workers).acquire_owned().await else {
+            break;
+        };
+        let context = Arc::clone(&context);
+        let shared = Arc::clone(&shared);
+        tokio::spawn(async move {
+            let result = handle_connection(stream, &context).await;
+            drop(permit);
+
+            let events = match result {
+                Ok(events) => events.into_iter().map(Ok).collect(),
+                Err(e) => vec![Err(e)],
+            };
+            let sender = shared.lock().await;
+            for event in events {
+                info!("Sending new event");
+                if sender.send(event).await.is_err() {
+                    return;
+                }
+            }
+        });
+    }
+
+    info!("Stop listening.");
+}
+
+/// Periodically re-subscribe to the topics whose lease is about to expire,
+/// until the event stream is dropped.
+async fn renew(subscriber: Subscriber, sender: mpsc::Sender<Result<Event, Error>>) {
+    loop {
+        tokio::select! {
+            () = tokio::time::sleep(RENEWAL_INTERVAL) => (),
+            () = sender.closed() => return,
+        }
+
+        let subscriber = subscriber.clone();
+        let renewals = tokio::task::spawn_blocking(move || renew_due(&subscriber))
+            .await
+            .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()));
+        for renewal in renewals {
+            if sender.send(Err(renewal)).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    context: &Arc<Context>,
+) -> Result<Vec<Event>, Error> {
+    let peer = stream.peer_addr().ok();
+    let mut decoder = Decoder::request(MAX_BODY_SIZE);
+
+    receive(&mut stream, peer, &mut decoder, context)
+        .await
+        .map_err(|e| ConnectionError::new(peer, decoder.received(), e).into())
+}
+
+async fn receive(
+    stream: &mut TcpStream,
+    peer: Option<SocketAddr>,
+    decoder: &mut Decoder,
+    context: &Arc<Context>,
+) -> Result<Vec<Event>, StageError> {
+    let read = framing::read_message_async(stream, decoder);
+    let received = match tokio::time::timeout(READ_TIMEOUT, read).await {
+        Ok(received) => received
+            .inspect_err(|e| error!("Error reading message: {e}"))
+            .at(Stage::Read),
+        Err(_) => {
+            error!("Timed out reading message");
+            Err(FramingError::Io(io::ErrorKind::TimedOut.into())).at(Stage::Read)
+        }
+    };
+
+    // The handling may block on the verify hook, or on saving the dedup
+    // and subscription state
+    let (events, reply) = match received {
+        Ok(received) => {
+            let context = Arc::clone(context);
+            tokio::task::spawn_blocking(move || {
+                // The reply is derived from the result unless the handling set one
+                let mut reply = None;
+                let events = handle_message(received, peer, &mut reply, &context);
+                (events, reply)
+            })
+            .await
+            .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))
+        }
+        Err(e) => (Err(e), None),
+    };
+
+    if let Some(reply) = reply.or_else(|| Reply::for_result(&events)) {
+        info!("Sending: {reply}");
+        let write = async {
+            stream.write_all(&reply.to_bytes()).await?;
+            stream.flush().await
+        };
+        tokio::time::timeout(WRITE_TIMEOUT, write)
+            .await
+            .unwrap_or_else(|_| Err(io::ErrorKind::TimedOut.into()))
+            .at(Stage::Reply)?;
+    }
+
+    events
+}
@@ -1,13 +1,64 @@
 #![allow(unused)]
+use crate::classifier::{Class, Classifier};
+use crate::client::{self, Client};
+use crate::dedup::{Dedup, DedupConfig};
 use crate::error::BuilderError;
+use crate::hub::Hub;
+use crate::registry::Registry;
+use crate::source::SourcePolicy;
+use crate::storage::Storage;
+use crate::subscription::{Callbacks, Verification, VerifyHook};
+use crate::url::Url;
 use crate::HookListener;
-use std::{fmt::Display, io, net::TcpListener, sync::Arc};
+use std::{
+    fmt::{self, Display},
+    io,
+    net::TcpListener,
+    sync::Arc,
+};
 
-#[derive(Debug, Default)]
+const DEFAULT_HUB: &str = "https://pubsubhubbub.appspot.com/";
+const DEFAULT_WORKERS: usize = 4;
+
+#[derive(Default)]
 pub struct HookListenerBuilder {
     listener: Option<TcpListener>,
     callback: Option<String>,
     new_only: bool,
+    hub: Option<String>,
+    secret: Option<String>,
+    verify_hook: Option<VerifyHook>,
+    root_certificates: Vec<Vec<u8>>,
+    routes: Vec<String>,
+    callback_per_topic: bool,
+    dedup: Option<DedupConfig>,
+    classifier: Classifier,
+    forward: Option<Vec<Class>>,
+    workers: Option<usize>,
+    source_policy: Option<SourcePolicy>,
+    storage: Option<Arc<dyn Storage>>,
+}
+
+impl fmt::Debug for HookListenerBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HookListenerBuilder")
+            .field("listener", &self.listener)
+            .field("callback", &self.callback)
+            .field("new_only", &self.new_only)
+            .field("hub", &self.hub)
+            .field("secret", &self.secret.as_ref().map(|_| "<redacted>"))
+            .field("verify_hook", &self.verify_hook.as_ref().map(|_| "Fn"))
+            .field("root_certificates", &self.root_certificates.len())
+            .field("routes", &self.routes)
+            .field("callback_per_topic", &self.callback_per_topic)
+            .field("dedup", &self.dedup)
+            .field("classifier", &self.classifier)
+            .field("forward", &self.forward)
+            .field("workers", &self.workers)
+            .field("source_policy", &self.source_policy)
+            .field("storage", &self.storage.as_ref().map(|_| "Storage"))
+            .finish()
+    }
 }
 
 impl HookListenerBuilder {
@@ -22,16 +73,191 @@ impl HookListenerBuilder {
         self
     }
 
+    /// Give each topic its own callback url, made of the callback url path
+    /// and an opaque id, e.g. `https://example.com/hook/9f86d081884c7d65`.
+    ///
+    /// Notifications are then attributed to their subscription, and
+    /// deliveries to the callback of an inactive subscription are rejected.
+    pub fn callback_per_topic(mut self, callback_per_topic: bool) -> Self {
+        self.callback_per_topic = callback_per_topic;
+        self
+    }
+
+    /// Accept callbacks on `path` in addition to the path of the callback url.
+    pub fn route(mut self, path: impl Into<String>) -> Self {
+        self.routes.push(path.into());
+        self
+    }
+
+    /// Only forward the new uploads, same as `forward([Class::NewUpload])`.
     pub fn new_only(mut self, new_only: bool) -> Self {
         self.new_only = new_only;
         self
     }
 
+    /// Url of the hub the subscription requests are sent to.
+    ///
+    /// Defaults to the Google hub, `https://pubsubhubbub.appspot.com/`.
+    pub fn hub(mut self, hub: impl Into<String>) -> Self {
+        self.hub = Some(hub.into());
+        self
+    }
+
+    /// Thresholds used to classify the notifications.
+    pub fn classifier(mut self, classifier: Classifier) -> Self {
+        self.classifier = classifier;
+        self
+    }
+
+    /// Classes of notifications sent by the listener, defaults to all of them.
+    pub fn forward(mut self, classes: impl IntoIterator<Item = Class>) -> Self {
+        self.forward = Some(classes.into_iter().collect());
+        self
+    }
+
+    /// Number of connections handled in parallel, defaults to 4.
+    ///
+    /// A slow client only holds up one worker, the others keep handling the
+    /// verifications and notifications.
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = Some(workers);
+        self
+    }
+
+    /// Drop the notifications of videos already delivered recently.
+    ///
+    /// YouTube sends several notifications for one upload, and hubs deliver
+    /// them again when the callback times out.
+    pub fn dedup(mut self, config: DedupConfig) -> Self {
+        self.dedup = Some(config);
+        self
+    }
+
+    /// Save the subscriptions to `storage`, e.g. a [`crate::prelude::FileStorage`].
+    ///
+    /// The subscriptions saved by a previous run are loaded on build and
+    /// their renewals resume, check [`HookListener::registry`] before
+    /// subscribing again.
+    pub fn storage(mut self, storage: impl Storage) -> Self {
+        self.storage = Some(Arc::new(storage));
+        self
+    }
+
+    /// Secret sent to the hub as `hub.secret` when subscribing.
+    ///
+    /// When set, every notification must carry a valid `X-Hub-Signature`
+    /// header or it is rejected.
+    pub fn secret(mut self, secret: impl Into<String>) -> Self {
+        self.secret = Some(secret.into());
+        self
+    }
+
+    /// Trust an additional root certificate, DER encoded, when connecting to
+    /// an `https` hub.
+    ///
+    /// The webpki root certificates are always trusted.
+    #[cfg(feature = "tls")]
+    pub fn root_certificate(mut self, der: impl Into<Vec<u8>>) -> Self {
+        self.root_certificates.push(der.into());
+        self
+    }
+
+    /// Policy deciding which requests are trusted, the others are refused
+    /// with `403 Forbidden`.
+    ///
+    /// Defaults to [`SourcePolicy::googlebot`], which only trusts the Google
+    /// hub, when using the default hub; and to [`SourcePolicy::any`] with
+    /// another hub.
+    pub fn source_policy(mut self, policy: SourcePolicy) -> Self {
+        self.source_policy = Some(policy);
+        self
+    }
+
+    /// Hook called on each verification of intent matching a request we
+    /// made; the verification is refused if it returns `false`.
+    pub fn verify_intent(
+        mut self,
+        hook: impl Fn(&Verification) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.verify_hook = Some(Arc::new(hook));
+        self
+    }
+
     pub fn build(self) -> Result<HookListener, BuilderError> {
+        // The spec requires the secret to be less than 200 bytes
+        if self
+            .secret
+            .as_ref()
+            .is_some_and(|secret| secret.len() >= 200)
+        {
+            return Err(BuilderError::SecretTooLong);
+        }
+
+        let callback = self.callback.ok_or(BuilderError::MissingCallback)?;
+        let callback_url = Url::parse(&callback)
+            .ok_or_else(|| BuilderError::InvalidCallbackUrl(callback.clone()))?;
+        let mut routes = self.routes;
+        routes.push(callback_url.path_only().to_string());
+        let callbacks = Callbacks::new(
+            callback.clone(),
+            callback_url.path_only().to_string(),
+            self.callback_per_topic,
+        );
+
+        let hub = self.hub.as_deref().unwrap_or(DEFAULT_HUB);
+        let hub = Url::parse(hub).ok_or_else(|| BuilderError::InvalidHubUrl(hub.to_string()))?;
+        // The requests of other hubs do not come from Googlebot
+        let source_policy = self.source_policy.unwrap_or_else(|| {
+            let default = Url::parse(DEFAULT_HUB).expect("valid default hub url");
+            if hub.scheme == default.scheme && hub.host.eq_ignore_ascii_case(&default.host) {
+                SourcePolicy::googlebot()
+            } else {
+                SourcePolicy::any()
+            }
+        });
+
+        #[cfg(feature = "tls")]
+        let client = Client {
+            tls: Some(Arc::new(client::tls_config(self.root_certificates)?)),
+        };
+        #[cfg(not(feature = "tls"))]
+        let client = Client::default();
+
+        let dedup = self
+            .dedup
+            .map(Dedup::new)
+            .transpose()
+            .map_err(BuilderError::Dedup)?
+            .map(Arc::new);
+
+        let registry = match self.storage {
+            Some(storage) => Registry::load(storage).map_err(BuilderError::Storage)?,
+            None => Registry::default(),
+        };
+        for registration in registry.list() {
+            if let Some(id) = &registration.callback_id {
+                callbacks.restore(id, &registration.topic);
+            }
+        }
+
         Ok(HookListener {
-            listener: Arc::new(self.listener.ok_or_else(|| BuilderError::MissingListener)?),
-            callback: self.callback.ok_or_else(|| BuilderError::MissingCallback)?,
+            listener: Arc::new(self.listener.ok_or(BuilderError::MissingListener)?),
+            callback,
             new_only: self.new_only,
+            hub: Arc::new(Hub {
+                url: hub,
+                secret: self.secret,
+                client,
+            }),
+            routes,
+            callbacks: Arc::new(callbacks),
+            dedup,
+            classifier: self.classifier,
+            forward: self.forward.unwrap_or_else(|| Class::ALL.to_vec()),
+            workers: self.workers.unwrap_or(DEFAULT_WORKERS).max(1),
+            registry: Arc::new(registry),
+            verify_hook: self.verify_hook,
+            source_policy,
         })
     }
 }
@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+use time::OffsetDateTime;
+
+/// Kind of change a notification is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Class {
+    /// The video was just uploaded.
+    NewUpload,
+    /// The title or description of a recent video changed.
+    MetadataUpdate,
+    /// Scheduled premiere or live stream, published in the future.
+    Scheduled,
+    /// Old video notified again, e.g. made public again.
+    Resurfaced,
+}
+
+impl Class {
+    pub const ALL: [Class; 4] = [
+        Self::NewUpload,
+        Self::MetadataUpdate,
+        Self::Scheduled,
+        Self::Resurfaced,
+    ];
+}
+
+/// Thresholds used to classify the notifications.
+#[derive(Debug, Clone)]
+pub struct Classifier {
+    /// A video updated less than this after being published is a new upload.
+    pub new_upload: Duration,
+    /// A video published more than this ago has resurfaced.
+    pub resurfaced: Duration,
+}
+
+impl Default for Classifier {
+    fn default() -> Self {
+        Self {
+            new_upload: Duration::from_secs(5 * 60),
+            resurfaced: Duration::from_secs(7 * 24 * 60 * 60),
+        }
+    }
+}
+
+impl Classifier {
+    pub fn classify(&self, published: OffsetDateTime, updated: OffsetDateTime) -> Class {
+        let now = OffsetDateTime::now_utc();
+
+        if published > now {
+            Class::Scheduled
+        } else if now - published > self.resurfaced {
+            Class::Resurfaced
+        } else if updated - published < self.new_upload {
+            Class::NewUpload
+        } else {
+            Class::MetadataUpdate
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use std::{
+    io::{self, prelude::*},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+#[cfg(feature = "tls")]
+use std::sync::Arc;
+
+use crate::{
+    error::ClientError,
+    framing::ReadTimeout,
+    prelude::Error,
+    url::{Scheme, Url},
+};
+
+/// Timeout of the connection, reads and writes to the hub.
+///
+/// The resolution of the host name is not covered.
+pub(crate) const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Outbound connection to a hub, over TLS for `https` urls.
+pub(crate) enum Connection {
+    Plain(TcpStream),
+    #[cfg(feature = "tls")]
+    Tls(Box<rustls::StreamOwned<rustls::ClientConnection, TcpStream>>),
+}
+
+impl ReadTimeout for Connection {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Self::Plain(stream) => stream.set_read_timeout(timeout),
+            #[cfg(feature = "tls")]
+            Self::Tls(stream) => stream.sock.set_read_timeout(timeout),
+        }
+    }
+}
+
+/// Client configuration used to connect to the hubs.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Client {
+    #[cfg(feature = "tls")]
+    pub(crate) tls: Option<Arc<rustls::ClientConfig>>,
+}
+
+impl Client {
+    /// Open a connection to the host and port of `url`.
+    ///
+    /// `https` urls are only supported with the `tls` feature, the server
+    /// certificate is validated against the configured root certificates.
+    pub(crate) fn connect(&self, url: &Url) -> Result<Connection, Error> {
+        let stream = connect_timeout(&url.host, url.port)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+
+        match url.scheme {
+            Scheme::Http => Ok(Connection::Plain(stream)),
+            #[cfg(feature = "tls")]
+            Scheme::Https => {
+                let config = self.tls.clone().ok_or(ClientError::TlsDisabled)?;
+                let server_name = rustls::pki_types::ServerName::try_from(url.host.clone())
+                    .map_err(|_| ClientError::InvalidServerName(url.host.clone()))?;
+                let connection =
+                    rustls::ClientConnection::new(config, server_name).map_err(ClientError::Tls)?;
+
+                Ok(Connection::Tls(Box::new(rustls::StreamOwned::new(
+                    connection, stream,
+                ))))
+            }
+            #[cfg(not(feature = "tls"))]
+            Scheme::Https => Err(ClientError::TlsDisabled.into()),
+        }
+    }
+}
+
+/// Connect to the first reachable address of `host`, waiting up to
+/// [`TIMEOUT`] for each.
+fn connect_timeout(host: &str, port: u16) -> io::Result<TcpStream> {
+    let mut last_error = None;
+    for address in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = Some(e),
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, format!("No address for {host}"))
+    }))
+}
+
+/// Build the TLS configuration trusting the webpki roots and `extra_roots`,
+/// given as DER encoded certificates.
+#[cfg(feature = "tls")]
+pub(crate) fn tls_config(extra_roots: Vec<Vec<u8>>) -> Result<rustls::ClientConfig, rustls::Error> {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    for der in extra_roots {
+        roots.add(rustls::pki_types::CertificateDer::from(der))?;
+    }
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    Ok(config)
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(stream) => stream.read(buf),
+            #[cfg(feature = "tls")]
+            Self::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(stream) => stream.write(buf),
+            #[cfg(feature = "tls")]
+            Self::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Plain(stream) => stream.flush(),
+            #[cfg(feature = "tls")]
+            Self::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tls"))]
+mod tests {
+    use std::{net::TcpListener, thread, time::Instant};
+
+    use rcgen::{BasicConstraints, CertificateParams, IsCa, Issuer, KeyPair};
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+
+    use super::*;
+    use crate::framing::{self, Decoder, MAX_BODY_SIZE};
+
+    /// Certificate of a custom root CA, and a server certificate for
+    /// `localhost` with its key, signed by that CA.
+    fn certificates() -> (Vec<u8>, CertificateDer<'static>, PrivateKeyDer<'static>) {
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+
+        let key = KeyPair::generate().unwrap();
+        let params = CertificateParams::new(vec!["localhost".to_string()]).unwrap();
+        let issuer = Issuer::from_params(&ca_params, &ca_key);
+        let cert = params.signed_by(&key, &issuer).unwrap();
+
+        (
+            ca.der().to_vec(),
+            cert.der().clone(),
+            PrivatePkcs8KeyDer::from(key.serialize_der()).into(),
+        )
+    }
+
+    /// TLS stand-in hub accepting one connection and replying `202 Accepted`.
+    fn hub(
+        cert: CertificateDer<'static>,
+        key: PrivateKeyDer<'static>,
+    ) -> (Url, thread::JoinHandle<()>) {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let config = rustls::ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert], key)
+            .unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!(
+            "https://localhost:{}/",
+            listener.local_addr().unwrap().port()
+        ))
+        .unwrap();
+
+        let hub = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let connection = rustls::ServerConnection::new(Arc::new(config)).unwrap();
+            let mut stream = rustls::StreamOwned::new(connection, stream);
+
+            // The handshake fails when the client rejects the certificate
+            let mut request = [0; 1024];
+            if stream.read(&mut request).is_ok() {
+                let _ = stream
+                    .write_all(b"HTTP/1.1 202 Accepted\r\nContent-Length: 0\r\n\r\n")
+                    .and_then(|()| stream.flush());
+                stream.conn.send_close_notify();
+                let _ = stream.flush();
+            }
+        });
+
+        (url, hub)
+    }
+
+    fn request(client: &Client, url: &Url) -> Result<framing::RawMessage, Error> {
+        let mut connection = client.connect(url)?;
+        connection.write_all(b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\n\r\n")?;
+        connection.flush()?;
+
+        let deadline = Instant::now() + TIMEOUT;
+        let reply = framing::read_message(
+            &mut connection,
+            &mut Decoder::response(MAX_BODY_SIZE),
+            deadline,
+        )?;
+        Ok(reply)
+    }
+
+    #[test]
+    fn trusts_custom_root_certificate() {
+        let (ca, cert, key) = certificates();
+        let (url, hub) = hub(cert, key);
+
+        let client = Client {
+            tls: Some(Arc::new(tls_config(vec![ca]).unwrap())),
+        };
+        let reply = request(&client, &url).unwrap();
+        assert!(reply.head.starts_with(b"HTTP/1.1 202 Accepted"));
+        hub.join().unwrap();
+    }
+
+    #[test]
+    fn rejects_unknown_certificate() {
+        let (_, cert, key) = certificates();
+        let (url, hub) = hub(cert, key);
+
+        let client = Client {
+            tls: Some(Arc::new(tls_config(vec![]).unwrap())),
+        };
+        let error = request(&client, &url).unwrap_err();
+        assert!(format!("{error:?}").contains("UnknownIssuer"), "{error:?}");
+        hub.join().unwrap();
+    }
+}
@@ -0,0 +1,92 @@
+use std::{collections::VecDeque, path::PathBuf, sync::Mutex, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::error::StateFileError;
+use crate::state_file;
+
+/// Configuration of the deduplication of notifications.
+#[derive(Debug, Clone)]
+pub struct DedupConfig {
+    /// How long a video id is remembered.
+    pub window: Duration,
+    /// Maximum number of video ids remembered, the oldest are forgotten first.
+    pub capacity: usize,
+    /// File the remembered video ids are saved to, and loaded from on start.
+    pub path: Option<PathBuf>,
+}
+
+impl Default for DedupConfig {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(24 * 60 * 60),
+            capacity: 1024,
+            path: None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct State {
+    seen: VecDeque<Seen>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Seen {
+    video_id: String,
+    /// Unix timestamp of the first delivery.
+    at: i64,
+}
+
+/// Drop the notifications of videos already delivered within the window.
+#[derive(Debug)]
+pub(crate) struct Dedup {
+    config: DedupConfig,
+    state: Mutex<State>,
+}
+
+impl Dedup {
+    /// Create the dedup stage, loading the saved state if any.
+    pub(crate) fn new(config: DedupConfig) -> Result<Self, StateFileError> {
+        let state = match &config.path {
+            Some(path) => state_file::load(path)?.unwrap_or_default(),
+            None => State::default(),
+        };
+
+        Ok(Self {
+            config,
+            state: Mutex::new(state),
+        })
+    }
+
+    /// Record a delivery of `video_id`.
+    ///
+    /// Returns whether it is the first delivery within the window.
+    pub(crate) fn first_delivery(&self, video_id: &str) -> Result<bool, StateFileError> {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let window = i64::try_from(self.config.window.as_secs()).unwrap_or(i64::MAX);
+
+        let mut state = self.state.lock().unwrap();
+        state
+            .seen
+            .retain(|seen| now.saturating_sub(seen.at) < window);
+        if state.seen.iter().any(|seen| seen.video_id == video_id) {
+            return Ok(false);
+        }
+
+        state.seen.push_back(Seen {
+            video_id: video_id.to_string(),
+            at: now,
+        });
+        while state.seen.len() > self.config.capacity {
+            state.seen.pop_front();
+        }
+
+        if let Some(path) = &self.config.path {
+            state_file::save(path, &*state)?;
+        }
+
+        Ok(true)
+    }
+}
@@ -1,5 +1,10 @@
+use std::{fmt, net::SocketAddr};
+
 use crate::prelude::Notification;
 
+/// Maximum number of raw bytes kept in a [`ConnectionError`].
+const EXCERPT_SIZE: usize = 512;
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("Builder error")]
@@ -14,6 +19,115 @@ pub enum Error {
     HandleConnection(#[from] HandleConnectionError),
     #[error("Notfication error")]
     Notification(#[from] NotificationError),
+    #[error("Signature verification failed")]
+    Signature(#[from] SignatureError),
+    #[error("HTTP framing error")]
+    Framing(#[from] FramingError),
+    #[error("Deduplication error")]
+    Dedup(#[from] StateFileError),
+    #[error("Hub connection error")]
+    Client(#[from] ClientError),
+    #[error("Failed to renew subscription to {topic}")]
+    Renewal {
+        topic: String,
+        #[source]
+        source: Box<Error>,
+    },
+    #[error("Error while handling connection from {}", display_peer(.0.peer))]
+    Connection(#[from] ConnectionError),
+    #[error("{0} threads still running after the shutdown deadline")]
+    ShutdownTimeout(usize),
+}
+
+/// Stage of the handling of a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Receiving the message.
+    Read,
+    /// Parsing the message, its parameters or its feed.
+    Parse,
+    /// Checking the route, source, signature or verification of intent.
+    Verify,
+    /// Building the notifications from the feed.
+    Notify,
+    /// Sending the reply.
+    Reply,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Read => "read",
+                Self::Parse => "parse",
+                Self::Verify => "verify",
+                Self::Notify => "notify",
+                Self::Reply => "reply",
+            }
+        )
+    }
+}
+
+/// Error of the listener while handling a connection, with its context.
+#[derive(Debug, thiserror::Error)]
+#[error("{stage} failed{}", .request_line.as_ref().map(|line| format!(" for {line}")).unwrap_or_default())]
+pub struct ConnectionError {
+    /// Address of the peer, unless it disconnected before being read.
+    pub peer: Option<SocketAddr>,
+    pub stage: Stage,
+    /// Request or status line, if it was received.
+    pub request_line: Option<String>,
+    /// Start of the raw message received, at most 512 bytes.
+    pub excerpt: String,
+    #[source]
+    pub source: Box<Error>,
+}
+
+impl ConnectionError {
+    pub(crate) fn new(peer: Option<SocketAddr>, received: &[u8], error: StageError) -> Self {
+        let request_line = received
+            .split(|b| *b == b'\n')
+            .next()
+            .filter(|line| line.ends_with(b"\r"))
+            .map(|line| String::from_utf8_lossy(&line[..line.len() - 1]).into_owned());
+        let excerpt = &received[..received.len().min(EXCERPT_SIZE)];
+
+        Self {
+            peer,
+            stage: error.stage,
+            request_line,
+            excerpt: String::from_utf8_lossy(excerpt).into_owned(),
+            source: Box::new(error.error),
+        }
+    }
+}
+
+fn display_peer(peer: Option<SocketAddr>) -> String {
+    peer.map_or_else(|| "unknown peer".to_string(), |peer| peer.to_string())
+}
+
+/// Error of a stage of the handling of a connection, before its context is
+/// attached.
+#[derive(Debug)]
+pub(crate) struct StageError {
+    pub(crate) stage: Stage,
+    pub(crate) error: Error,
+}
+
+/// Record the stage an error happened at.
+pub(crate) trait At<T> {
+    fn at(self, stage: Stage) -> Result<T, StageError>;
+}
+
+impl<T, E: Into<Error>> At<T> for Result<T, E> {
+    fn at(self, stage: Stage) -> Result<T, StageError> {
+        self.map_err(|e| StageError {
+            stage,
+            error: e.into(),
+        })
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -24,6 +138,19 @@ pub enum BuilderError {
     MissingListener,
     #[error("Missing callback URL")]
     MissingCallback,
+    #[error("Invalid callback URL {0}")]
+    InvalidCallbackUrl(String),
+    #[error("Invalid hub URL {0}")]
+    InvalidHubUrl(String),
+    #[error("Secret must be less than 200 bytes")]
+    SecretTooLong,
+    #[error("Cannot load the deduplication state")]
+    Dedup(#[source] StateFileError),
+    #[error("Cannot load the subscription state")]
+    Storage(#[source] StorageError),
+    #[cfg(feature = "tls")]
+    #[error("Invalid TLS configuration")]
+    Tls(#[from] rustls::Error),
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -46,6 +173,10 @@ pub enum HandleConnectionError {
     FormatUtf8Error(#[from] std::string::FromUtf8Error),
     #[error("Message has no body")]
     NoBodyError,
+    #[error("Method {0} not allowed")]
+    MethodNotAllowed(String),
+    #[error("Untrusted source: {0}")]
+    UntrustedSource(String),
     #[error("Send error")]
     SendError(#[from] Box<std::sync::mpsc::SendError<Notification>>),
 }
@@ -56,4 +187,77 @@ pub enum NotificationError {
     MissingParameter(String),
     #[error("OffsetDateTime parse error")]
     DateTimeError(#[from] time::error::Parse),
+    #[error("XML error")]
+    Xml(#[from] quick_xml::Error),
+    #[error("XML encoding error")]
+    Encoding(#[from] quick_xml::encoding::EncodingError),
+    #[error("Malformed XML: {0}")]
+    Malformed(String),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SourceError {
+    #[error("Invalid CIDR range {0}")]
+    InvalidCidr(String),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SignatureError {
+    #[error("Missing X-Hub-Signature header")]
+    Missing,
+    #[error("Malformed signature {0}")]
+    Malformed(String),
+    #[error("Unsupported signature method {0}")]
+    UnsupportedMethod(String),
+    #[error("Signature does not match body")]
+    Mismatch,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ClientError {
+    #[error("https hubs require the `tls` feature")]
+    TlsDisabled,
+    #[error("Invalid server name {0}")]
+    InvalidServerName(String),
+    #[cfg(feature = "tls")]
+    #[error("TLS error")]
+    Tls(#[source] rustls::Error),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FramingError {
+    #[error("I/O error")]
+    Io(#[from] std::io::Error),
+    #[error("Connection closed before the end of the message")]
+    UnexpectedEof,
+    #[error("Message head is too large")]
+    HeadTooLarge,
+    #[error("Message body is too large")]
+    BodyTooLarge,
+    #[error("Invalid Content-Length {0}")]
+    InvalidContentLength(String),
+    #[error("Invalid chunk {0}")]
+    InvalidChunk(String),
+    #[error("Unsupported Transfer-Encoding {0}")]
+    UnsupportedTransferEncoding(String),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum StateFileError {
+    #[error("Cannot read or write the state file")]
+    Io(#[from] std::io::Error),
+    #[error("Cannot deserialize the state")]
+    Deserialize(#[from] toml::de::Error),
+    #[error("Cannot serialize the state")]
+    Serialize(#[from] toml::ser::Error),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum StorageError {
+    #[error("Cannot load or save the state file")]
+    File(#[from] StateFileError),
+    #[error("Invalid {0} in the state")]
+    Invalid(String),
+    #[error("Storage backend error")]
+    Backend(#[source] Box<dyn std::error::Error + Send + Sync>),
 }
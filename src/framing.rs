@@ -0,0 +1,588 @@
+use std::{
+    io::{self, prelude::*},
+    net::TcpStream,
+    time::{Duration, Instant},
+};
+
+use crate::error::FramingError;
+
+/// Maximum size of the request/status line and headers.
+const MAX_HEAD_SIZE: usize = 16 * 1024;
+/// Maximum size of a decoded body.
+pub(crate) const MAX_BODY_SIZE: usize = 4 * 1024 * 1024;
+
+const READ_SIZE: usize = 8 * 1024;
+
+/// HTTP/1.1 message split into its head and decoded body.
+#[derive(Debug)]
+pub(crate) struct RawMessage {
+    /// Start line and headers, without the empty line.
+    pub(crate) head: Vec<u8>,
+    pub(crate) body: Vec<u8>,
+}
+
+/// Progress of the decoder after being fed some bytes.
+#[derive(Debug)]
+pub(crate) enum Status {
+    /// More bytes are needed.
+    Incomplete,
+    /// The client waits for `100 Continue` before sending the body.
+    Continue,
+    Complete(RawMessage),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    Head,
+    Length(usize),
+    /// Body of a response without length, delimited by the connection closing.
+    UntilClose,
+    ChunkSize,
+    ChunkData(usize),
+    ChunkDataEnd,
+    Trailers,
+    Done,
+}
+
+/// Incremental decoder of an HTTP/1.1 message.
+///
+/// Bytes are fed as they are received, in chunks of any size. The body is
+/// delimited by `Content-Length` or decoded from `Transfer-Encoding: chunked`.
+#[derive(Debug)]
+pub(crate) struct Decoder {
+    buf: Vec<u8>,
+    /// Position of the first byte of `buf` not decoded yet.
+    pos: usize,
+    state: State,
+    is_response: bool,
+    expect_continue: bool,
+    max_body_size: usize,
+    head: Vec<u8>,
+    body: Vec<u8>,
+}
+
+impl Decoder {
+    pub(crate) fn request(max_body_size: usize) -> Self {
+        Self::new(false, max_body_size)
+    }
+
+    pub(crate) fn response(max_body_size: usize) -> Self {
+        Self::new(true, max_body_size)
+    }
+
+    fn new(is_response: bool, max_body_size: usize) -> Self {
+        Self {
+            buf: vec![],
+            pos: 0,
+            state: State::Head,
+            is_response,
+            expect_continue: false,
+            max_body_size,
+            head: vec![],
+            body: vec![],
+        }
+    }
+
+    /// Feed received bytes to the decoder.
+    pub(crate) fn feed(&mut self, data: &[u8]) -> Result<Status, FramingError> {
+        self.buf.extend_from_slice(data);
+        self.decode()
+    }
+
+    /// Every byte received so far, decoded or not.
+    pub(crate) fn received(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Signal that the peer closed the connection.
+    pub(crate) fn finish(&mut self) -> Result<RawMessage, FramingError> {
+        match self.state {
+            State::UntilClose => {
+                self.state = State::Done;
+                Ok(self.take())
+            }
+            _ => Err(FramingError::UnexpectedEof),
+        }
+    }
+
+    fn decode(&mut self) -> Result<Status, FramingError> {
+        loop {
+            match self.state {
+                State::Head => {
+                    let Some(end) = find(&self.buf, b"\r\n\r\n") else {
+                        if self.buf.len() > MAX_HEAD_SIZE {
+                            return Err(FramingError::HeadTooLarge);
+                        }
+                        return Ok(Status::Incomplete);
+                    };
+                    if end > MAX_HEAD_SIZE {
+                        return Err(FramingError::HeadTooLarge);
+                    }
+                    self.head = self.buf[..end].to_vec();
+                    self.pos = end + 4;
+                    self.state = self.body_state()?;
+
+                    if self.expect_continue && !matches!(self.state, State::Length(0)) {
+                        self.expect_continue = false;
+                        return Ok(Status::Continue);
+                    }
+                }
+                State::Length(0) => self.state = State::Done,
+                State::Length(remaining) => {
+                    let n = self.take_body(remaining)?;
+                    if n == 0 {
+                        return Ok(Status::Incomplete);
+                    }
+                    self.state = State::Length(remaining - n);
+                }
+                State::UntilClose => {
+                    self.take_body(usize::MAX)?;
+                    return Ok(Status::Incomplete);
+                }
+                State::ChunkSize => {
+                    let Some(line) = self.take_line()? else {
+                        return Ok(Status::Incomplete);
+                    };
+                    // Chunk extensions are ignored
+                    let size = line.split(';').next().unwrap_or_default().trim();
+                    let size = usize::from_str_radix(size, 16)
+                        .map_err(|_| FramingError::InvalidChunk(size.to_string()))?;
+                    self.state = if size == 0 {
+                        State::Trailers
+                    } else {
+                        State::ChunkData(size)
+                    };
+                }
+                State::ChunkData(remaining) => {
+                    let n = self.take_body(remaining)?;
+                    if n == 0 {
+                        return Ok(Status::Incomplete);
+                    }
+                    self.state = if n == remaining {
+                        State::ChunkDataEnd
+                    } else {
+                        State::ChunkData(remaining - n)
+                    };
+                }
+                State::ChunkDataEnd => {
+                    if self.buf.len() < self.pos + 2 {
+                        return Ok(Status::Incomplete);
+                    }
+                    if &self.buf[self.pos..self.pos + 2] != b"\r\n" {
+                        return Err(FramingError::InvalidChunk(
+                            "missing CRLF after chunk data".to_string(),
+                        ));
+                    }
+                    self.pos += 2;
+                    self.state = State::ChunkSize;
+                }
+                State::Trailers => {
+                    // Trailer fields are ignored, the message ends with an empty line
+                    let Some(line) = self.take_line()? else {
+                        return Ok(Status::Incomplete);
+                    };
+                    if line.is_empty() {
+                        self.state = State::Done;
+                    }
+                }
+                State::Done => return Ok(Status::Complete(self.take())),
+            }
+        }
+    }
+
+    /// Find how the body is delimited from the headers.
+    fn body_state(&mut self) -> Result<State, FramingError> {
+        let head = String::from_utf8_lossy(&self.head);
+        let mut content_length = None;
+        let mut chunked = false;
+
+        for line in head.split("\r\n").skip(1) {
+            let Some((name, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("Content-Length") {
+                let length: usize = value
+                    .parse()
+                    .map_err(|_| FramingError::InvalidContentLength(value.to_string()))?;
+                if content_length.is_some_and(|previous| previous != length) {
+                    return Err(FramingError::InvalidContentLength(value.to_string()));
+                }
+                content_length = Some(length);
+            } else if name.eq_ignore_ascii_case("Transfer-Encoding") {
+                // Other codings would leave the body encoded, only a single
+                // `chunked` is supported
+                if chunked || !value.eq_ignore_ascii_case("chunked") {
+                    return Err(FramingError::UnsupportedTransferEncoding(value.to_string()));
+                }
+                chunked = true;
+            } else if name.eq_ignore_ascii_case("Expect") {
+                self.expect_continue = value.eq_ignore_ascii_case("100-continue");
+            }
+        }
+
+        // Transfer-Encoding overrides Content-Length
+        let state = match (chunked, content_length) {
+            (true, _) => State::ChunkSize,
+            (false, Some(length)) if length > self.max_body_size => {
+                return Err(FramingError::BodyTooLarge)
+            }
+            (false, Some(length)) => State::Length(length),
+            (false, None) if self.is_response => State::UntilClose,
+            (false, None) => State::Length(0),
+        };
+
+        Ok(state)
+    }
+
+    /// Move up to `max` received bytes to the body.
+    fn take_body(&mut self, max: usize) -> Result<usize, FramingError> {
+        let n = max.min(self.buf.len() - self.pos);
+        if self.body.len() + n > self.max_body_size {
+            return Err(FramingError::BodyTooLarge);
+        }
+        self.body
+            .extend_from_slice(&self.buf[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+
+    /// Take a line ending with CRLF, if it was received entirely.
+    fn take_line(&mut self) -> Result<Option<String>, FramingError> {
+        let Some(end) = find(&self.buf[self.pos..], b"\r\n") else {
+            if self.buf.len() - self.pos > MAX_HEAD_SIZE {
+                return Err(FramingError::HeadTooLarge);
+            }
+            return Ok(None);
+        };
+        let line = String::from_utf8_lossy(&self.buf[self.pos..self.pos + end]).into_owned();
+        self.pos += end + 2;
+        Ok(Some(line))
+    }
+
+    fn take(&mut self) -> RawMessage {
+        RawMessage {
+            head: std::mem::take(&mut self.head),
+            body: std::mem::take(&mut self.body),
+        }
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Stream whose reads can time out.
+pub(crate) trait ReadTimeout {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl ReadTimeout for TcpStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
+/// Read a message from `stream`, answering `Expect: 100-continue`.
+///
+/// The whole message must be received before `deadline`, a peer sending it
+/// byte by byte cannot hold the connection longer.
+pub(crate) fn read_message<S: Read + Write + ReadTimeout>(
+    stream: &mut S,
+    decoder: &mut Decoder,
+    deadline: Instant,
+) -> Result<RawMessage, FramingError> {
+    let mut buf = [0; READ_SIZE];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(FramingError::Io(io::ErrorKind::TimedOut.into()));
+        }
+        stream.set_read_timeout(Some(remaining))?;
+
+        let n = match stream.read(&mut buf) {
+            Ok(0) => return decoder.finish(),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            // Some servers close TLS connections without notifying it
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return decoder.finish(),
+            Err(e) => return Err(FramingError::Io(e)),
+        };
+
+        match decoder.feed(&buf[..n])? {
+            Status::Incomplete => (),
+            Status::Continue => {
+                stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+                stream.flush()?;
+                // The body may already be in the buffer
+                if let Status::Complete(message) = decoder.feed(&[])? {
+                    return Ok(message);
+                }
+            }
+            Status::Complete(message) => return Ok(message),
+        }
+    }
+}
+
+/// Read a message from an async `stream`, answering `Expect: 100-continue`.
+#[cfg(feature = "tokio")]
+pub(crate) async fn read_message_async<S>(
+    stream: &mut S,
+    decoder: &mut Decoder,
+) -> Result<RawMessage, FramingError>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let mut buf = [0; READ_SIZE];
+    loop {
+        let n = match stream.read(&mut buf).await {
+            Ok(0) => return decoder.finish(),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(FramingError::Io(e)),
+        };
+
+        match decoder.feed(&buf[..n])? {
+            Status::Incomplete => (),
+            Status::Continue => {
+                stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n").await?;
+                stream.flush().await?;
+                // The body may already be in the buffer
+                if let Status::Complete(message) = decoder.feed(&[])? {
+                    return Ok(message);
+                }
+            }
+            Status::Complete(message) => return Ok(message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENT_LENGTH: &[u8] =
+        b"POST /hook HTTP/1.1\r\nHost: example.com\r\nContent-Length: 11\r\n\r\nhello world";
+    const CHUNKED: &[u8] = b"POST /hook HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+        5;name=value\r\nhello\r\n1\r\n \r\n5\r\nworld\r\n0\r\nExpires: never\r\n\r\n";
+    const EXPECT_CONTINUE: &[u8] =
+        b"POST /hook HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 11\r\n\r\nhello world";
+
+    /// Feed `parts` one after the other until the message is complete.
+    ///
+    /// Returns the message and whether `100 Continue` was requested.
+    fn decode(decoder: &mut Decoder, parts: &[&[u8]]) -> (RawMessage, bool) {
+        let mut continued = false;
+        for part in parts {
+            let mut status = decoder.feed(part).unwrap();
+            if let Status::Continue = status {
+                assert!(!continued, "100 Continue requested twice");
+                continued = true;
+                status = decoder.feed(&[]).unwrap();
+            }
+            if let Status::Complete(message) = status {
+                return (message, continued);
+            }
+        }
+        panic!("message is incomplete");
+    }
+
+    /// Decode `message` split at every position, and one byte at a time.
+    fn decode_splits(message: &[u8]) -> Vec<(RawMessage, bool)> {
+        let mut decoded: Vec<_> = (0..=message.len())
+            .map(|i| {
+                let (a, b) = message.split_at(i);
+                decode(&mut Decoder::request(MAX_BODY_SIZE), &[a, b])
+            })
+            .collect();
+
+        let bytes: Vec<_> = message.chunks(1).collect();
+        decoded.push(decode(&mut Decoder::request(MAX_BODY_SIZE), &bytes));
+        decoded
+    }
+
+    #[test]
+    fn content_length() {
+        for (message, continued) in decode_splits(CONTENT_LENGTH) {
+            assert!(message.head.ends_with(b"Content-Length: 11"));
+            assert_eq!(message.body, b"hello world");
+            assert!(!continued);
+        }
+    }
+
+    #[test]
+    fn chunked_with_extensions_and_trailers() {
+        for (message, continued) in decode_splits(CHUNKED) {
+            assert!(message.head.ends_with(b"Transfer-Encoding: chunked"));
+            assert_eq!(message.body, b"hello world");
+            assert!(!continued);
+        }
+    }
+
+    #[test]
+    fn expect_continue() {
+        for (message, continued) in decode_splits(EXPECT_CONTINUE) {
+            assert_eq!(message.body, b"hello world");
+            assert!(continued);
+        }
+    }
+
+    #[test]
+    fn expect_continue_without_body() {
+        let message = b"POST /hook HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 0\r\n\r\n";
+        for (message, continued) in decode_splits(message) {
+            assert!(message.body.is_empty());
+            assert!(!continued);
+        }
+    }
+
+    #[test]
+    fn response_until_close() {
+        let mut decoder = Decoder::response(MAX_BODY_SIZE);
+        for byte in b"HTTP/1.1 200 OK\r\n\r\nhello".chunks(1) {
+            assert!(matches!(decoder.feed(byte), Ok(Status::Incomplete)));
+        }
+        assert_eq!(decoder.finish().unwrap().body, b"hello");
+    }
+
+    #[test]
+    fn unexpected_eof() {
+        let mut decoder = Decoder::request(MAX_BODY_SIZE);
+        decoder.feed(&CONTENT_LENGTH[..50]).unwrap();
+        assert!(matches!(decoder.finish(), Err(FramingError::UnexpectedEof)));
+    }
+
+    #[test]
+    fn head_too_large() {
+        let mut decoder = Decoder::request(MAX_BODY_SIZE);
+        let mut head = b"GET /hook HTTP/1.1\r\nX-Padding: ".to_vec();
+        head.resize(MAX_HEAD_SIZE + 1, b'a');
+        assert!(matches!(
+            decoder.feed(&head),
+            Err(FramingError::HeadTooLarge)
+        ));
+
+        // Also when the end of the head arrives with the last bytes
+        let mut decoder = Decoder::request(MAX_BODY_SIZE);
+        head.extend_from_slice(b"\r\n\r\n");
+        assert!(matches!(
+            decoder.feed(&head),
+            Err(FramingError::HeadTooLarge)
+        ));
+    }
+
+    #[test]
+    fn body_too_large() {
+        let mut decoder = Decoder::request(10);
+        assert!(matches!(
+            decoder.feed(CONTENT_LENGTH),
+            Err(FramingError::BodyTooLarge)
+        ));
+
+        // The size of a chunked body is only known while decoding it
+        let mut decoder = Decoder::request(10);
+        let result = CHUNKED
+            .chunks(1)
+            .map(|byte| decoder.feed(byte))
+            .find(|result| !matches!(result, Ok(Status::Incomplete)));
+        assert!(matches!(result, Some(Err(FramingError::BodyTooLarge))));
+    }
+
+    #[test]
+    fn invalid_chunk() {
+        let mut decoder = Decoder::request(MAX_BODY_SIZE);
+        let message = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n";
+        assert!(matches!(
+            decoder.feed(message),
+            Err(FramingError::InvalidChunk(size)) if size == "zz"
+        ));
+
+        let mut decoder = Decoder::request(MAX_BODY_SIZE);
+        let message = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nabc\r\n";
+        assert!(matches!(
+            decoder.feed(message),
+            Err(FramingError::InvalidChunk(_))
+        ));
+    }
+
+    #[test]
+    fn unsupported_transfer_encoding() {
+        for encoding in ["gzip", "gzip, chunked", "chunked, chunked"] {
+            let mut decoder = Decoder::request(MAX_BODY_SIZE);
+            let message = format!("POST / HTTP/1.1\r\nTransfer-Encoding: {encoding}\r\n\r\n");
+            assert!(matches!(
+                decoder.feed(message.as_bytes()),
+                Err(FramingError::UnsupportedTransferEncoding(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn read_message_deadline() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut stream, _) = listener.accept().unwrap();
+
+        // A byte is sent before each read times out, but not the whole message
+        let slow = std::thread::spawn(move || {
+            for byte in CONTENT_LENGTH.chunks(1).take(20) {
+                if client.write_all(byte).is_err() {
+                    return;
+                }
+                std::thread::sleep(Duration::from_millis(20));
+            }
+        });
+
+        let deadline = Instant::now() + Duration::from_millis(100);
+        let result = read_message(&mut stream, &mut Decoder::request(MAX_BODY_SIZE), deadline);
+        assert!(matches!(
+            result,
+            Err(FramingError::Io(e)) if matches!(e.kind(), io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock)
+        ));
+        assert!(Instant::now() < deadline + Duration::from_millis(100));
+        drop(stream);
+        slow.join().unwrap();
+    }
+
+    #[test]
+    fn read_message_answers_continue() {
+        struct Peer {
+            input: io::Cursor<&'static [u8]>,
+            output: Vec<u8>,
+        }
+        impl Read for Peer {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                // One byte at a time
+                let len = buf.len().min(1);
+                self.input.read(&mut buf[..len])
+            }
+        }
+        impl ReadTimeout for Peer {
+            fn set_read_timeout(&self, _: Option<Duration>) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        impl Write for Peer {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.output.write(buf)
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut peer = Peer {
+            input: io::Cursor::new(EXPECT_CONTINUE),
+            output: vec![],
+        };
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let message =
+            read_message(&mut peer, &mut Decoder::request(MAX_BODY_SIZE), deadline).unwrap();
+        assert_eq!(message.body, b"hello world");
+        assert_eq!(peer.output, b"HTTP/1.1 100 Continue\r\n\r\n");
+    }
+}
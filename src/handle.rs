@@ -0,0 +1,197 @@
+use std::{
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, Condvar, Mutex},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use tracing::{info, warn};
+
+use crate::error::Error;
+use crate::hub::SubscribeOutcome;
+use crate::registry::Registry;
+use crate::subscription::Subscriber;
+use crate::Mode;
+
+/// Interval between two checks of the threads or verifications waited for.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Stop signal shared by the threads of a running listener.
+#[derive(Debug)]
+pub(crate) struct Running {
+    stopped: Mutex<bool>,
+    /// Notified when the listener is stopped.
+    stopping: Condvar,
+    listener: Arc<TcpListener>,
+}
+
+impl Running {
+    pub(crate) fn new(listener: Arc<TcpListener>) -> Self {
+        Self {
+            stopped: Mutex::new(false),
+            stopping: Condvar::new(),
+            listener,
+        }
+    }
+
+    pub(crate) fn listener(&self) -> &TcpListener {
+        &self.listener
+    }
+
+    pub(crate) fn is_stopped(&self) -> bool {
+        *self.stopped.lock().unwrap()
+    }
+
+    /// Wait up to `timeout` for the listener to be stopped.
+    ///
+    /// Returns whether it is stopped.
+    pub(crate) fn wait_stopped(&self, timeout: Duration) -> bool {
+        let stopped = self.stopped.lock().unwrap();
+        let (stopped, _) = self
+            .stopping
+            .wait_timeout_while(stopped, timeout, |stopped| !*stopped)
+            .unwrap();
+        *stopped
+    }
+
+    /// Stop accepting connections.
+    ///
+    /// The accepting thread is blocked until a connection comes in, so one
+    /// is made to wake it up.
+    pub(crate) fn stop(&self) {
+        {
+            let mut stopped = self.stopped.lock().unwrap();
+            if *stopped {
+                return;
+            }
+            *stopped = true;
+        }
+        self.stopping.notify_all();
+
+        let Ok(mut addr) = self.listener.local_addr() else {
+            return;
+        };
+        if addr.ip().is_unspecified() {
+            let ip = match addr {
+                SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+                SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+            };
+            addr.set_ip(ip);
+        }
+        if let Err(e) = TcpStream::connect_timeout(&addr, Duration::from_secs(1)) {
+            warn!("Cannot wake up the listener on {addr}: {e}");
+        }
+    }
+}
+
+/// Handle of a listener started with [`crate::HookListener::listen`].
+///
+/// Dropping the handle leaves the listener running in the background.
+#[derive(Debug)]
+pub struct ListenerHandle {
+    pub(crate) running: Arc<Running>,
+    pub(crate) accept: JoinHandle<()>,
+    pub(crate) workers: Vec<JoinHandle<()>>,
+    pub(crate) renewal: JoinHandle<()>,
+    pub(crate) subscriber: Subscriber,
+}
+
+impl ListenerHandle {
+    /// Stop accepting connections, without waiting for the connections being
+    /// handled.
+    pub fn stop(&self) {
+        self.running.stop();
+    }
+
+    /// Whether the listener stopped accepting connections, either because
+    /// [`ListenerHandle::stop`] was called or because the receiver of the
+    /// events was dropped.
+    pub fn is_stopped(&self) -> bool {
+        self.running.is_stopped()
+    }
+
+    /// Subscriptions of the listener.
+    pub fn registry(&self) -> &Registry {
+        &self.subscriber.registry
+    }
+
+    /// Wait for the listener to stop and every connection to be handled.
+    pub fn join(self) {
+        let _ = self.accept.join();
+        for worker in self.workers {
+            let _ = worker.join();
+        }
+        let _ = self.renewal.join();
+    }
+
+    /// Stop accepting connections and wait for the connections being handled.
+    ///
+    /// # Errors:
+    ///
+    /// The connections were not all handled before `deadline`, they are
+    /// left to finish in the background.
+    pub fn shutdown(self, deadline: Duration) -> Result<(), Error> {
+        self.shutdown_until(Instant::now() + deadline)
+    }
+
+    /// Unsubscribe from every topic before shutting down.
+    ///
+    /// Connections keep being accepted until the hub verified the
+    /// unsubscriptions, or `deadline` passed. Unsubscriptions that fail are
+    /// logged and do not prevent the shutdown.
+    ///
+    /// See [`ListenerHandle::shutdown`].
+    pub fn unsubscribe_and_shutdown(self, deadline: Duration) -> Result<(), Error> {
+        let deadline = Instant::now() + deadline;
+
+        let mut pending = vec![];
+        for topic in self.subscriber.registry.subscribed() {
+            match self.subscriber.request(&topic, Mode::Unsubscribe, None) {
+                Ok(SubscribeOutcome::Accepted) => pending.push(topic),
+                Ok(outcome) => warn!("Cannot unsubscribe from {topic}: {outcome}"),
+                Err(e) => warn!("Cannot unsubscribe from {topic}: {e}"),
+            }
+        }
+
+        while Instant::now() < deadline
+            && pending.iter().any(|topic| {
+                self.subscriber
+                    .registry
+                    .is_pending(topic, Mode::Unsubscribe)
+            })
+        {
+            std::thread::sleep(POLL_INTERVAL);
+        }
+
+        self.shutdown_until(deadline)
+    }
+
+    fn shutdown_until(self, deadline: Instant) -> Result<(), Error> {
+        info!("Shutting down the listener");
+        self.running.stop();
+
+        let threads = std::iter::once(self.accept)
+            .chain(self.workers)
+            .chain(std::iter::once(self.renewal));
+        let running = threads
+            .filter_map(|thread| join_until(thread, deadline).err())
+            .count();
+
+        if running > 0 {
+            return Err(Error::ShutdownTimeout(running));
+        }
+        Ok(())
+    }
+}
+
+/// Join `thread` if it finishes before `deadline`, otherwise give it back.
+fn join_until(thread: JoinHandle<()>, deadline: Instant) -> Result<(), JoinHandle<()>> {
+    while !thread.is_finished() {
+        if Instant::now() >= deadline {
+            return Err(thread);
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    let _ = thread.join();
+    Ok(())
+}
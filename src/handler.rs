@@ -0,0 +1,118 @@
+use std::{
+    ops::ControlFlow,
+    sync::{
+        mpsc::{Receiver, Sender, SyncSender},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use crate::error::Error;
+use crate::handle::ListenerHandle;
+use crate::notification::Event;
+
+/// Receives the events and errors of a listener.
+///
+/// Calls are serialized, the events of a connection are handled one after
+/// the other without events of another connection in between.
+///
+/// Implemented for std [`Sender`] and [`SyncSender`], whose listener stops
+/// when the receiver is dropped, and for closures wrapped with
+/// [`handler_fn`].
+pub trait Handler: Send + 'static {
+    /// Handle an event, or an error of the listener.
+    ///
+    /// Returning [`ControlFlow::Break`] stops the listener.
+    fn handle(&mut self, event: Result<Event, Error>) -> ControlFlow<()>;
+}
+
+impl Handler for Sender<Result<Event, Error>> {
+    fn handle(&mut self, event: Result<Event, Error>) -> ControlFlow<()> {
+        match self.send(event) {
+            Ok(()) => ControlFlow::Continue(()),
+            Err(_) => ControlFlow::Break(()),
+        }
+    }
+}
+
+/// Bounded channel, the listener waits for the receiver when it is full.
+impl Handler for SyncSender<Result<Event, Error>> {
+    fn handle(&mut self, event: Result<Event, Error>) -> ControlFlow<()> {
+        match self.send(event) {
+            Ok(()) => ControlFlow::Continue(()),
+            Err(_) => ControlFlow::Break(()),
+        }
+    }
+}
+
+/// Handler calling a closure, see [`handler_fn`].
+#[derive(Debug, Clone)]
+pub struct HandlerFn<F>(F);
+
+/// Use a closure as a [`Handler`].
+pub fn handler_fn<F>(f: F) -> HandlerFn<F>
+where
+    F: FnMut(Result<Event, Error>) -> ControlFlow<()> + Send + 'static,
+{
+    HandlerFn(f)
+}
+
+impl<F> Handler for HandlerFn<F>
+where
+    F: FnMut(Result<Event, Error>) -> ControlFlow<()> + Send + 'static,
+{
+    fn handle(&mut self, event: Result<Event, Error>) -> ControlFlow<()> {
+        (self.0)(event)
+    }
+}
+
+/// Handler shared by the threads of a listener.
+pub(crate) type SharedHandler = std::sync::Arc<Mutex<dyn Handler>>;
+
+/// Hand `events` to the handler, stopping at the first break.
+pub(crate) fn deliver(
+    handler: &Mutex<dyn Handler>,
+    events: impl IntoIterator<Item = Result<Event, Error>>,
+) -> ControlFlow<()> {
+    let mut handler = handler.lock().unwrap();
+    events
+        .into_iter()
+        .try_for_each(|event| handler.handle(event))
+}
+
+/// Iterator over the events of a listener started with
+/// [`crate::HookListener::listen_iter`].
+///
+/// The listener stops when the iterator is dropped, and the iterator ends
+/// once the listener stopped, e.g. with `events.handle().stop()`.
+#[derive(Debug)]
+pub struct Events {
+    pub(crate) receiver: Receiver<Result<Event, Error>>,
+    pub(crate) handle: ListenerHandle,
+}
+
+impl Events {
+    /// Handle of the listener, to stop it.
+    pub fn handle(&self) -> &ListenerHandle {
+        &self.handle
+    }
+
+    /// Wait up to `timeout` for the next event.
+    pub fn next_timeout(&self, timeout: Duration) -> Option<Result<Event, Error>> {
+        self.receiver.recv_timeout(timeout).ok()
+    }
+}
+
+impl Drop for Events {
+    fn drop(&mut self) {
+        self.handle.stop();
+    }
+}
+
+impl Iterator for Events {
+    type Item = Result<Event, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.receiver.recv().ok()
+    }
+}
@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+
+/// Header fields of a message, looked up by case-insensitive name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Headers {
+    /// Values by lowercase name.
+    fields: HashMap<String, String>,
+}
+
+impl Headers {
+    /// Add a field; the value of a repeated field is appended to the
+    /// previous ones, separated by a comma.
+    pub(crate) fn insert(&mut self, name: &str, value: &str) {
+        self.fields
+            .entry(name.to_ascii_lowercase())
+            .and_modify(|values| {
+                values.push_str(", ");
+                values.push_str(value);
+            })
+            .or_insert_with(|| value.to_string());
+    }
+
+    /// Get the value of the field `name`, in any case.
+    pub(crate) fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .get(&name.to_ascii_lowercase())
+            .map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn case_insensitive() {
+        let mut headers = Headers::default();
+        headers.insert("Content-Type", "text/plain");
+        assert_eq!(headers.get("content-type"), Some("text/plain"));
+        assert_eq!(headers.get("CONTENT-TYPE"), Some("text/plain"));
+        assert_eq!(headers.get("Content-Length"), None);
+    }
+
+    #[test]
+    fn repeated_fields_are_combined() {
+        let mut headers = Headers::default();
+        headers.insert("From", "googlebot(at)googlebot.com");
+        headers.insert("from", "someone@example.com");
+        assert_eq!(
+            headers.get("From"),
+            Some("googlebot(at)googlebot.com, someone@example.com")
+        );
+    }
+}
@@ -0,0 +1,249 @@
+use std::{
+    fmt,
+    io::prelude::*,
+    time::{Duration, Instant},
+};
+
+use time::{format_description::well_known::Rfc2822, OffsetDateTime};
+use tracing::{debug, info};
+
+use crate::{
+    client::{self, Client},
+    error::ParseError,
+    framing::{self, Decoder, MAX_BODY_SIZE},
+    prelude::*,
+    response::Response,
+    url::Url,
+};
+
+/// Hub that subscription requests are sent to.
+pub(crate) struct Hub {
+    pub(crate) url: Url,
+    pub(crate) secret: Option<String>,
+    pub(crate) client: Client,
+}
+
+impl fmt::Debug for Hub {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hub")
+            .field("url", &self.url)
+            .field("secret", &self.secret.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+impl Hub {
+    /// Send a subscription/unsubscription request for `topic_url`.
+    pub(crate) fn send(
+        &self,
+        callback_url: &str,
+        topic_url: &str,
+        mode: Mode,
+        lease: Option<Duration>,
+    ) -> Result<SubscribeOutcome, Error> {
+        let hub = &self.url;
+
+        info!(
+            r#"
+Making subscription request to {hub} with:
+hub.callback={}
+hub.mode={}
+hub.topic={}"#,
+            &callback_url, mode, &topic_url
+        );
+
+        // Building the subscription request
+        let mut body = form_urlencoded::Serializer::new(String::new());
+        body.append_pair("hub.callback", callback_url)
+            .append_pair("hub.mode", &mode.to_string())
+            .append_pair("hub.topic", topic_url);
+        if let Some(lease) = lease {
+            body.append_pair("hub.lease_seconds", &lease.as_secs().to_string());
+        }
+        let params = body.finish();
+        let mut body = form_urlencoded::Serializer::for_suffix(params.clone(), 0);
+        if let Some(secret) = &self.secret {
+            body.append_pair("hub.secret", secret);
+        }
+        let body = body.finish();
+        let len = body.len();
+        let head = |authorization: &str| {
+            format!(
+                "POST {} HTTP/1.1\r\nHost: {}\r\n{authorization}Content-Type: application/x-www-form-urlencoded\r\nContent-Length: {len}\r\nConnection: close\r\n\r\n",
+                hub.path,
+                hub.host_header()
+            )
+        };
+        let authorization = hub
+            .authorization()
+            .map(|authorization| format!("Authorization: {authorization}\r\n"));
+        // The credentials and the secret are kept out of the logs
+        let redacted_authorization = match authorization {
+            Some(_) => "Authorization: <redacted>\r\n",
+            None => "",
+        };
+        let redacted_secret = match self.secret {
+            Some(_) => "&hub.secret=<redacted>",
+            None => "",
+        };
+        debug!(
+            "{:?}",
+            format!("{}{params}{redacted_secret}", head(redacted_authorization))
+        );
+        let post_request = head(authorization.as_deref().unwrap_or_default()) + &body;
+
+        // Connect a socket and send the request
+        let mut stream = self.client.connect(hub)?;
+        stream.write_all(post_request.as_bytes())?;
+        stream.flush()?;
+
+        // Read the hub's reply, the connection is closed once it is sent
+        let deadline = Instant::now() + client::TIMEOUT;
+        let reply =
+            framing::read_message(&mut stream, &mut Decoder::response(MAX_BODY_SIZE), deadline)?;
+        let head = String::from_utf8_lossy(&reply.head);
+        let body = String::from_utf8_lossy(&reply.body);
+        let response = Response::parse(&head, &body)?;
+        debug!("Hub replied: {response}");
+
+        let outcome = SubscribeOutcome::from_response(&response)?;
+        info!("Subscription request outcome: {outcome:?}");
+
+        Ok(outcome)
+    }
+}
+
+/// Reply of the hub to a subscription/unsubscription request.
+///
+/// The request being accepted does not mean the subscription is active,
+/// the hub still has to verify the intent by calling the callback.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubscribeOutcome {
+    /// `202 Accepted`, or any other `2XX` status.
+    Accepted,
+    /// The hub refused the request.
+    Rejected {
+        status: u16,
+        body: String,
+        retry_after: Option<RetryAfter>,
+    },
+    /// The request must be sent to another hub.
+    Redirect {
+        status: u16,
+        location: Option<String>,
+    },
+}
+
+/// When a rejected request can be retried, from the `Retry-After` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryAfter {
+    Delay(Duration),
+    Date(OffsetDateTime),
+}
+
+impl SubscribeOutcome {
+    fn from_response(response: &Response) -> Result<Self, ParseError> {
+        let status_code = response.status_line.status_code;
+        let status: u16 = status_code
+            .parse()
+            .map_err(|_| ParseError::HeaderError(format!("Invalid status code {status_code}")))?;
+
+        let outcome = match status {
+            200..=299 => Self::Accepted,
+            300..=399 => Self::Redirect {
+                status,
+                location: response.headers.get("Location").map(str::to_string),
+            },
+            _ => Self::Rejected {
+                status,
+                body: response.body.unwrap_or_default().to_string(),
+                retry_after: response
+                    .headers
+                    .get("Retry-After")
+                    .and_then(RetryAfter::parse),
+            },
+        };
+
+        Ok(outcome)
+    }
+
+    pub fn is_accepted(&self) -> bool {
+        matches!(self, Self::Accepted)
+    }
+
+    /// Whether the hub refused the request for now but may accept it later,
+    /// i.e. it timed out, is rate limiting or is unavailable.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            Self::Rejected {
+                status: 408 | 429 | 500..=599,
+                ..
+            }
+        )
+    }
+}
+
+/// Longest wait before retrying a rejected request, whatever the hub asks.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(24 * 60 * 60);
+
+impl RetryAfter {
+    /// When the request can be retried, for a rejection received at `now`,
+    /// at most [`MAX_RETRY_AFTER`] later.
+    pub(crate) fn after(self, now: OffsetDateTime) -> OffsetDateTime {
+        let latest = now + MAX_RETRY_AFTER;
+        match self {
+            Self::Delay(delay) => now
+                .checked_add(delay.try_into().unwrap_or(time::Duration::MAX))
+                .map_or(latest, |at| at.min(latest)),
+            Self::Date(date) => date.clamp(now, latest),
+        }
+    }
+
+    /// Parse either a delay in seconds or an HTTP date.
+    fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        if let Ok(seconds) = value.parse() {
+            return Some(Self::Delay(Duration::from_secs(seconds)));
+        }
+        OffsetDateTime::parse(value, &Rfc2822).ok().map(Self::Date)
+    }
+}
+
+impl fmt::Display for SubscribeOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Accepted => write!(f, "Accepted"),
+            Self::Rejected { status, body, .. } => write!(f, "Rejected ({status}): {body}"),
+            Self::Redirect { status, location } => write!(
+                f,
+                "Redirected ({status}) to {}",
+                location.as_deref().unwrap_or("unknown location")
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_after_is_clamped() {
+        let now = OffsetDateTime::now_utc();
+        let delay = RetryAfter::parse("120").unwrap();
+        assert_eq!(delay.after(now), now + Duration::from_secs(120));
+
+        let latest = now + MAX_RETRY_AFTER;
+        assert_eq!(
+            RetryAfter::parse("99999999999999").unwrap().after(now),
+            latest
+        );
+        assert_eq!(RetryAfter::Delay(Duration::MAX).after(now), latest);
+
+        let date = RetryAfter::parse("Fri, 31 Dec 9999 23:59:59 +0000").unwrap();
+        assert_eq!(date.after(now), latest);
+        let date = RetryAfter::parse("Wed, 21 Oct 2015 07:28:00 +0000").unwrap();
+        assert_eq!(date.after(now), now);
+    }
+}
@@ -1,31 +1,66 @@
+#[cfg(feature = "tokio")]
+mod async_listener;
 mod buidler;
+mod classifier;
+mod client;
+mod dedup;
 mod error;
+mod framing;
+mod handle;
+mod handler;
+mod headers;
+mod hub;
 mod message;
 mod notification;
 mod parse;
 pub mod prelude;
+mod registry;
+mod reply;
 mod request;
 mod response;
+mod signature;
+mod source;
+mod state_file;
+mod storage;
+mod subscription;
+mod url;
 
 use std::{
     fmt,
     fs::File,
-    io::{prelude::*, BufReader},
-    net::{TcpListener, TcpStream},
-    sync::{mpsc::Sender, Arc},
-    time::Duration,
+    io::prelude::*,
+    net::{SocketAddr, TcpListener, TcpStream},
+    str::FromStr,
+    sync::{
+        mpsc::{self, SyncSender},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
 };
 
+use classifier::{Class, Classifier};
+use dedup::Dedup;
+use framing::{Decoder, RawMessage, MAX_BODY_SIZE};
+use handle::Running;
+use handler::{deliver, SharedHandler};
+use hub::Hub;
 use message::Message;
 use prelude::*;
+use registry::{Failure, Registry};
+use reply::Reply;
 use request::Request;
 use response::Response;
+use subscription::{Callbacks, Subscriber, VerifyHook, MAX_LEASE};
 use tracing::{debug, error, info, warn};
 
 use crate::buidler::HookListenerBuilder;
-use crate::error::{Error::SubscriptionError, HandleConnectionError, ParseError};
+use crate::error::{
+    At, ConnectionError, Error::SubscriptionError, HandleConnectionError, ParseError, Stage,
+    StageError,
+};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Mode {
     Subscribe,
     Unsubscribe,
@@ -44,11 +79,56 @@ impl fmt::Display for Mode {
     }
 }
 
-#[derive(Debug)]
+impl FromStr for Mode {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "subscribe" => Ok(Self::Subscribe),
+            "unsubscribe" => Ok(Self::Unsubscribe),
+            _ => Err(ParseError::ParameterError(format!("Invalid hub.mode: {s}"))),
+        }
+    }
+}
+
 pub struct HookListener {
     pub listener: Arc<TcpListener>,
     pub callback: String,
     pub new_only: bool,
+    pub(crate) classifier: Classifier,
+    /// Classes of notifications sent, ignored if `new_only` is set.
+    pub(crate) forward: Vec<Class>,
+    /// Number of connections handled in parallel.
+    pub(crate) workers: usize,
+    pub(crate) hub: Arc<Hub>,
+    /// Paths the callbacks are accepted on.
+    pub(crate) routes: Vec<String>,
+    pub(crate) callbacks: Arc<Callbacks>,
+    pub(crate) dedup: Option<Arc<Dedup>>,
+    pub(crate) registry: Arc<Registry>,
+    pub(crate) verify_hook: Option<VerifyHook>,
+    /// Decides which requests are trusted.
+    pub(crate) source_policy: SourcePolicy,
+}
+
+impl fmt::Debug for HookListener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HookListener")
+            .field("listener", &self.listener)
+            .field("callback", &self.callback)
+            .field("new_only", &self.new_only)
+            .field("classifier", &self.classifier)
+            .field("forward", &self.forward)
+            .field("workers", &self.workers)
+            .field("hub", &self.hub)
+            .field("routes", &self.routes)
+            .field("callbacks", &self.callbacks)
+            .field("dedup", &self.dedup)
+            .field("registry", &self.registry)
+            .field("verify_hook", &self.verify_hook.as_ref().map(|_| "Fn"))
+            .field("source_policy", &self.source_policy)
+            .finish()
+    }
 }
 
 impl HookListener {
@@ -57,145 +137,394 @@ impl HookListener {
     }
 
     /// Start listening for incoming streams.
-    pub fn listen(&self, sender: &Sender<Result<Notification, Error>>) {
+    ///
+    /// Every event, or error, is given to the `handler`. See [`Handler`] for
+    /// the ready-made handlers.
+    ///
+    /// Connections are handled in parallel by a pool of worker threads. The
+    /// events of a connection are handled together, in the order of the
+    /// feed; connections are handled in the order they complete, not the
+    /// order they are accepted.
+    ///
+    /// A second thread renews the subscriptions before their lease expires,
+    /// renewal failures are handled as [`Error::Renewal`].
+    ///
+    /// The listener runs until it is stopped with the returned handle, or
+    /// the handler breaks, e.g. when the receiver of a channel is dropped.
+    pub fn listen(&self, handler: impl Handler) -> ListenerHandle {
         info!("Start listening.");
 
-        let listener = Arc::clone(&self.listener);
-        let sender = sender.clone();
-        let new_only = self.new_only;
-
-        std::thread::spawn(move || {
-            for stream in listener.incoming() {
-                match stream {
-                    Ok(stream) => match handle_connection(stream, new_only) {
-                        Ok(reponse) => {
-                            if let Some(notification) = reponse {
-                                info!("Sending new notification");
-                                sender.send(Ok(notification)).unwrap();
-                            }
-                        }
-                        Err(e) => sender.send(Err(e)).unwrap(),
-                    },
-                    Err(e) => sender.send(Err(Error::TcpError(e))).unwrap(),
-                }
+        let handler: SharedHandler = Arc::new(Mutex::new(handler));
+        let running = Arc::new(Running::new(Arc::clone(&self.listener)));
+        let renewal = self.spawn_renewal(&handler, &running);
+        let (queue, workers) = self.spawn_workers(&handler, &running);
+
+        let accept = {
+            let running = Arc::clone(&running);
+            std::thread::spawn(move || accept(&running, &queue, &handler))
+        };
+
+        ListenerHandle {
+            running,
+            accept,
+            workers,
+            renewal,
+            subscriber: self.subscriber(),
+        }
+    }
+
+    /// Start listening for incoming streams, iterating over the events.
+    ///
+    /// See [`HookListener::listen`].
+    pub fn listen_iter(&self) -> Events {
+        let (sender, receiver) = mpsc::channel();
+        let handle = self.listen(sender);
+
+        Events { receiver, handle }
+    }
+
+    /// Listen for incoming streams on the caller's thread, until the
+    /// handler breaks.
+    ///
+    /// See [`HookListener::listen`].
+    pub fn listen_blocking(&self, handler: impl Handler) {
+        info!("Start listening.");
+
+        let handler: SharedHandler = Arc::new(Mutex::new(handler));
+        let running = Arc::new(Running::new(Arc::clone(&self.listener)));
+        let renewal = self.spawn_renewal(&handler, &running);
+        let (queue, workers) = self.spawn_workers(&handler, &running);
+
+        accept(&running, &queue, &handler);
+
+        running.stop();
+        drop(queue);
+        for worker in workers {
+            let _ = worker.join();
+        }
+        let _ = renewal.join();
+    }
+
+    /// Spawn the threads handling the connections pushed to the returned
+    /// queue.
+    fn spawn_workers(
+        &self,
+        handler: &SharedHandler,
+        running: &Arc<Running>,
+    ) -> (SyncSender<TcpStream>, Vec<JoinHandle<()>>) {
+        let context = Arc::new(self.context());
+
+        // Connections waiting for a worker, accepting blocks while it is full
+        let (queue, connections) = mpsc::sync_channel::<TcpStream>(self.workers);
+        let connections = Arc::new(Mutex::new(connections));
+
+        let workers = (0..self.workers)
+            .map(|_| {
+                let connections = Arc::clone(&connections);
+                let context = Arc::clone(&context);
+                let handler = Arc::clone(handler);
+                let running = Arc::clone(running);
+
+                std::thread::spawn(move || loop {
+                    let stream = connections.lock().unwrap().recv();
+                    let Ok(stream) = stream else {
+                        return;
+                    };
+
+                    let events = match handle_connection(stream, &context) {
+                        Ok(events) => events.into_iter().map(Ok).collect(),
+                        Err(e) => vec![Err(e)],
+                    };
+                    if deliver(&handler, events).is_break() {
+                        info!("Handler stopped, stop listening.");
+                        running.stop();
+                        return;
+                    }
+                })
+            })
+            .collect();
+
+        (queue, workers)
+    }
+
+    /// Periodically re-subscribe to the topics whose lease is about to expire.
+    ///
+    /// The thread stops with the listener.
+    fn spawn_renewal(&self, handler: &SharedHandler, running: &Arc<Running>) -> JoinHandle<()> {
+        let subscriber = self.subscriber();
+        let handler = Arc::clone(handler);
+        let running = Arc::clone(running);
+
+        std::thread::spawn(move || loop {
+            if running.wait_stopped(RENEWAL_INTERVAL) {
+                return;
             }
-        });
+
+            let renewals = renew_due(&subscriber).into_iter().map(Err);
+            if deliver(&handler, renewals).is_break() {
+                running.stop();
+                return;
+            }
+        })
+    }
+
+    /// State needed to handle the connections.
+    fn context(&self) -> Context {
+        Context {
+            classifier: self.classifier.clone(),
+            forward: if self.new_only {
+                vec![Class::NewUpload]
+            } else {
+                self.forward.clone()
+            },
+            secret: self.hub.secret.clone(),
+            routes: self.routes.clone(),
+            callbacks: Arc::clone(&self.callbacks),
+            dedup: self.dedup.clone(),
+            registry: Arc::clone(&self.registry),
+            verify_hook: self.verify_hook.clone(),
+            source_policy: self.source_policy.clone(),
+        }
+    }
+
+    pub(crate) fn subscriber(&self) -> Subscriber {
+        Subscriber {
+            hub: Arc::clone(&self.hub),
+            callbacks: Arc::clone(&self.callbacks),
+            registry: Arc::clone(&self.registry),
+        }
+    }
+
+    /// Send a subscription/unsubscription request to the hub for the videos
+    /// feed of a YouTube channel.
+    ///
+    /// See [`HookListener::subscribe_topic`].
+    pub fn subscribe(
+        &self,
+        id: impl AsRef<str>,
+        mode: Mode,
+        lease: Option<Duration>,
+    ) -> Result<SubscribeOutcome, Error> {
+        self.subscribe_topic(channel_topic(id.as_ref()), mode, lease)
     }
 
     /// Send a subscription/unsubscription request to the hub.
     ///
-    /// It sends a POST request to the hub with the formatted topic url
-    /// , the callback url and the subscription mode.
+    /// It sends a POST request to the hub with the topic url, the callback
+    /// url and the subscription mode.
     ///
-    /// # Panics:
+    /// If a secret is configured, it is sent as `hub.secret` so the hub
+    /// signs every notification it delivers.
     ///
-    /// Subscription mode is not "subscribe" or "unsubscribe".
+    /// `lease` is sent as `hub.lease_seconds`, the hub may grant a different
+    /// one. Either way, the subscription is renewed before it expires.
     ///
-    /// Publisher configuration is not found.
+    /// Returns the hub's reply to the request, an accepted request still has
+    /// to be verified by the hub before the subscription is active.
     ///
-    /// Request can not be streamed to the hub address.
-    pub fn subscribe(&self, id: impl AsRef<str>, mode: Mode) -> Result<(), Error> {
-        let id = id.as_ref();
-
-        info!("Initiating {mode} request with id: {id}");
-
-        //TODO: not hardcode this
-        let topic_url = format!("https://www.youtube.com/xml/feeds/videos.xml?channel_id={id}");
-        let hub = "https://pubsubhubbub.appspot.com";
-        let callback_url = &self.callback;
-
-        info!(
-            r#"
-Making subscription request to {hub} with:
-hub.callback={}
-hub.mode={}
-hub.topic={}"#,
-            &callback_url, mode, &topic_url
-        );
-
-        // Building the subscription request
-        let body = format!("hub.callback={callback_url}&hub.mode={mode}&hub.topic={topic_url}");
-        let len = body.as_bytes().len();
-        let post_request = format!("POST / HTTP/1.1\r\nHost: pubsubhubbub.appspot.com\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: {len}\r\n\r\n{body}");
-        debug!("{post_request:?}");
-
-        // Connect a socket and send the request
-        let hub_addr = format!(
-            "{}:80",
-            hub.trim_start_matches("https://")
-                .trim_start_matches("https://"),
-        );
-        let mut stream = TcpStream::connect(hub_addr)?;
-        stream.write_all(post_request.as_bytes())?;
-        stream.flush()?;
-
-        Ok(())
+    /// # Errors:
+    ///
+    /// Request can not be streamed to the hub address, or the reply can not
+    /// be read.
+    pub fn subscribe_topic(
+        &self,
+        topic_url: impl AsRef<str>,
+        mode: Mode,
+        lease: Option<Duration>,
+    ) -> Result<SubscribeOutcome, Error> {
+        self.subscriber().request(topic_url.as_ref(), mode, lease)
+    }
+
+    /// Get the lease of the subscription to `topic`.
+    pub fn lease(&self, topic: &str) -> Option<Lease> {
+        self.registry
+            .get(topic)
+            .map(|registration| registration.lease)
+    }
+
+    /// Get the leases of all subscriptions.
+    pub fn leases(&self) -> Vec<Lease> {
+        self.registry
+            .list()
+            .into_iter()
+            .map(|registration| registration.lease)
+            .collect()
+    }
+
+    /// Subscriptions of the listener.
+    pub fn registry(&self) -> &Registry {
+        &self.registry
     }
 }
 
-const BUF_SIZE: usize = 1024;
-
-fn handle_connection(mut stream: TcpStream, new_only: bool) -> Result<Option<Notification>, Error> {
-    stream.set_read_timeout(Some(Duration::from_secs(30)))?;
-    let mut buf_reader = BufReader::new(&mut stream);
-
-    let mut n_bytes = 0;
-    let mut received = vec![];
-    loop {
-        let mut buf = [0; BUF_SIZE];
-        match buf_reader.read(&mut buf) {
-            Ok(n) => {
-                n_bytes += n;
-                received.extend_from_slice(&buf);
-                if n < BUF_SIZE {
-                    break;
-                }
-            }
-            Err(e) => {
-                error!("Error reading buffer: {e}");
-                stream.flush()?;
-                return Err(Error::TcpError(e));
-            }
+/// Interval between two checks of the leases due for renewal.
+const RENEWAL_INTERVAL: Duration = Duration::from_secs(60);
+/// Delay before retrying a renewal that has not been verified.
+const RENEWAL_RETRY: Duration = Duration::from_secs(5 * 60);
+
+/// Topic url of the videos feed of a YouTube channel.
+fn channel_topic(id: &str) -> String {
+    format!("https://www.youtube.com/xml/feeds/videos.xml?channel_id={id}")
+}
+
+/// Re-subscribe to the topics whose lease is about to expire.
+///
+/// Returns the renewals that failed, as [`Error::Renewal`].
+fn renew_due(subscriber: &Subscriber) -> Vec<Error> {
+    let mut errors = vec![];
+    for lease in subscriber.registry.take_due(RENEWAL_RETRY) {
+        info!("Renewing subscription to {}", lease.topic);
+        let result = subscriber
+            .request(&lease.topic, Mode::Subscribe, lease.requested)
+            .and_then(|outcome| match outcome {
+                SubscribeOutcome::Accepted => Ok(()),
+                outcome => Err(SubscriptionError(outcome.to_string())),
+            });
+        if let Err(e) = result {
+            error!("Failed to renew subscription to {}: {e}", lease.topic);
+            errors.push(Error::Renewal {
+                topic: lease.topic,
+                source: Box::new(e),
+            });
+        }
+    }
+    errors
+}
+
+/// Accept connections and push them to `queue` until the listener is stopped.
+fn accept(running: &Running, queue: &SyncSender<TcpStream>, handler: &SharedHandler) {
+    for stream in running.listener().incoming() {
+        if running.is_stopped() {
+            break;
+        }
+
+        let accepted = match stream {
+            Ok(stream) => queue.send(stream).is_ok(),
+            Err(e) => deliver(handler, [Err(Error::TcpError(e))]).is_continue(),
+        };
+        if !accepted {
+            running.stop();
+            break;
         }
     }
-    info!("Received {n_bytes} bytes");
 
-    received.retain(|&b| b != 0);
-    let message = String::from_utf8(received).map_err(HandleConnectionError::FormatUtf8Error)?;
-    let message = Message::from_str(&message)?;
+    info!("Stop listening.");
+}
+
+/// State shared with the threads handling the connections.
+struct Context {
+    classifier: Classifier,
+    forward: Vec<Class>,
+    secret: Option<String>,
+    routes: Vec<String>,
+    callbacks: Arc<Callbacks>,
+    dedup: Option<Arc<Dedup>>,
+    registry: Arc<Registry>,
+    verify_hook: Option<VerifyHook>,
+    source_policy: SourcePolicy,
+}
+
+/// Time to receive a whole message before the connection is dropped.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+/// Time to send each write of a reply before the connection is dropped.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(30);
+
+fn handle_connection(mut stream: TcpStream, context: &Context) -> Result<Vec<Event>, Error> {
+    let peer = stream.peer_addr().ok();
+    let mut decoder = Decoder::request(MAX_BODY_SIZE);
+
+    receive(&mut stream, peer, &mut decoder, context)
+        .map_err(|e| ConnectionError::new(peer, decoder.received(), e).into())
+}
+
+fn receive(
+    stream: &mut TcpStream,
+    peer: Option<SocketAddr>,
+    decoder: &mut Decoder,
+    context: &Context,
+) -> Result<Vec<Event>, StageError> {
+    stream
+        .set_write_timeout(Some(WRITE_TIMEOUT))
+        .at(Stage::Read)?;
+
+    // The reply is derived from the result unless the handling set one
+    let mut reply = None;
+    let deadline = Instant::now() + READ_TIMEOUT;
+    let events = framing::read_message(stream, decoder, deadline)
+        .inspect_err(|e| error!("Error reading message: {e}"))
+        .at(Stage::Read)
+        .and_then(|received| handle_message(received, peer, &mut reply, context));
+
+    if let Some(reply) = reply.or_else(|| Reply::for_result(&events)) {
+        info!("Sending: {reply}");
+        stream.write_all(&reply.to_bytes()).at(Stage::Reply)?;
+        stream.flush().at(Stage::Reply)?;
+    }
+
+    events
+}
+
+/// Handle a received message, setting the `reply` to send back if any.
+///
+/// It does no IO so it is shared by the sync and async listeners.
+fn handle_message(
+    received: RawMessage,
+    peer: Option<SocketAddr>,
+    reply: &mut Option<Reply>,
+    context: &Context,
+) -> Result<Vec<Event>, StageError> {
+    info!(
+        "Received {} bytes",
+        received.head.len() + received.body.len()
+    );
+
+    let head = String::from_utf8(received.head)
+        .map_err(HandleConnectionError::FormatUtf8Error)
+        .at(Stage::Parse)?;
+    let body = String::from_utf8(received.body)
+        .map_err(HandleConnectionError::FormatUtf8Error)
+        .at(Stage::Parse)?;
+    let message = Message::parse(&head, &body).at(Stage::Parse)?;
 
     debug!("Message:\n{message:#?}");
 
-    let notification = match message {
-        Message::Request(request) => handle_request(request, stream, new_only)?,
+    let events = match message {
+        Message::Request(request) => handle_request(request, peer, reply, context)?,
         Message::Response(response) => {
-            handle_response(response)?;
-            None
+            handle_response(response, peer, context)?;
+            vec![]
         }
     };
 
-    debug!("End of handle_connection: {notification:#?}");
-    Ok(notification)
+    debug!("End of handle_message: {events:#?}");
+    Ok(events)
 }
 
 fn handle_request(
     request: Request,
-    mut stream: TcpStream,
-    new_only: bool,
-) -> Result<Option<Notification>, Error> {
-    let from = request
-        .headers
-        .get("From")
-        .ok_or_else(|| ParseError::NotFound("From header".to_string()))?;
-    if *from != "googlebot(at)googlebot.com" {
-        error!("unknown source : {from}");
-        return Err(HandleConnectionError::Empty.into());
+    peer: Option<SocketAddr>,
+    reply: &mut Option<Reply>,
+    context: &Context,
+) -> Result<Vec<Event>, StageError> {
+    // Per-topic callbacks are only accepted while the subscription is active
+    let path = request.request_line.path;
+    let subscription = context
+        .callbacks
+        .find(path)
+        .filter(|subscription| !context.registry.is_expired(&subscription.topic));
+    if subscription.is_none() && !context.routes.iter().any(|route| route == path) {
+        warn!("Unknown path: {path}");
+        return Err(ParseError::UriError).at(Stage::Verify);
+    }
+
+    if let Err(reason) = context.source_policy.check(peer, &request.headers) {
+        error!("Untrusted source: {reason}");
+        return Err(HandleConnectionError::UntrustedSource(reason)).at(Stage::Verify);
     }
 
     let method = request.request_line.method;
-    let _path = request.request_line.path;
 
-    let notification = match method {
+    let events = match method {
         // The hub send a GET request for the verification of intent
         // The scubscriber must answer with a 2XX status code and echo the hub.challenge value
         "GET" => {
@@ -203,63 +532,182 @@ fn handle_request(
             let params = request
                 .request_line
                 .params
-                .ok_or_else(|| ParseError::ParameterError("No paramater in request".to_string()))?;
-
-            if let Some(reason) = params.get("hub.reason") {
-                return Err(SubscriptionError((*reason).to_string()));
+                .ok_or_else(|| ParseError::ParameterError("No paramater in request".to_string()))
+                .at(Stage::Parse)?;
+
+            // The denial is acknowledged, then reported. It only ends a
+            // subscription sent on the callback of its topic, or on the shared
+            // callback while we wait for its verification
+            if params.get("hub.mode").is_some_and(|mode| mode == "denied") {
+                *reply = Some(Reply::new(200));
+                let reason = params
+                    .get("hub.reason")
+                    .map_or("Denied by the hub", String::as_str);
+                let topic = params.get("hub.topic").filter(|topic| match &subscription {
+                    Some(subscription) => subscription.topic == **topic,
+                    None => context.registry.is_pending(topic, Mode::Subscribe),
+                });
+                if let Some(topic) = topic {
+                    context.registry.denied(topic, reason);
+                }
+                return Err(SubscriptionError(reason.to_string())).at(Stage::Verify);
             }
 
             let challenge = params
                 .get("hub.challenge")
-                .ok_or_else(|| ParseError::NotFound("hub.challenge".to_string()))?;
-            let response = format!("HTTP/1.1 200 OK\r\n\r\n{challenge}");
-            info!("Sending: {response:?}");
-            stream.write_all(response.as_bytes())?;
+                .ok_or_else(|| ParseError::NotFound("hub.challenge".to_string()))
+                .at(Stage::Parse)?;
+            let topic = params
+                .get("hub.topic")
+                .ok_or_else(|| ParseError::NotFound("hub.topic".to_string()))
+                .at(Stage::Parse)?;
+            let mode: Mode = params
+                .get("hub.mode")
+                .ok_or_else(|| ParseError::NotFound("hub.mode".to_string()))
+                .at(Stage::Parse)?
+                .parse()
+                .at(Stage::Parse)?;
+            let lease = params
+                .get("hub.lease_seconds")
+                .map(|lease_seconds| {
+                    lease_seconds
+                        .parse()
+                        .ok()
+                        .map(Duration::from_secs)
+                        .filter(|lease| *lease <= MAX_LEASE)
+                        .ok_or_else(|| {
+                            ParseError::ParameterError(format!(
+                                "Invalid hub.lease_seconds: {lease_seconds}"
+                            ))
+                        })
+                })
+                .transpose()
+                .at(Stage::Parse)?;
+            let verification = Verification {
+                topic: (*topic).to_string(),
+                mode,
+                lease,
+            };
+
+            // Only confirm the requests we made, on the callback of their topic,
+            // and that the user hook approves
+            let expected = subscription
+                .as_ref()
+                .is_none_or(|subscription| subscription.topic == *topic)
+                && context.registry.is_pending(topic, mode);
+            let approved = expected
+                && context
+                    .verify_hook
+                    .as_ref()
+                    .is_none_or(|hook| hook(&verification));
+            if expected && !approved {
+                context.registry.failed(
+                    topic,
+                    mode,
+                    Failure::Denied("Refused by the verify hook".to_string()),
+                );
+            }
+            if !(approved && context.registry.take_pending(topic, mode)) {
+                warn!("Refusing verification of intent: {verification:?}");
+                return Err(SubscriptionError(format!(
+                    "Unexpected {mode} verification for {topic}"
+                )))
+                .at(Stage::Verify);
+            }
+
+            // Keep track of the lease granted by the hub to renew the subscription in time
+            match mode {
+                Mode::Subscribe => context.registry.activate(topic, lease),
+                Mode::Unsubscribe => {
+                    context.registry.unsubscribed(topic);
+                    context.callbacks.remove(topic);
+                }
+            }
+
+            *reply = Some(Reply::new(200).with_body(challenge.as_str()));
 
-            None
+            vec![]
         }
 
         // Request when a new resource is published
         "POST" => {
             info!("Received POST request");
 
-            let response = "HTTP/1.1 200 OK\r\n\r\n";
-            info!("Sending: {response}");
-            stream.write_all(response.as_bytes())?;
-
-            let notification = Notification::try_parse(
-                request
-                    .body
-                    .ok_or_else(|| HandleConnectionError::NoBodyError)?,
-            )?;
-
-            if new_only && !notification.is_new() {
-                info!("It's an updated video; pass");
-                None
-            } else {
-                Some(notification)
+            let body = request
+                .body
+                .ok_or(HandleConnectionError::NoBodyError)
+                .at(Stage::Parse)?;
+
+            // Unsigned or wrongly signed bodies are not parsed
+            if let Some(secret) = &context.secret {
+                if let Err(e) = signature::verify(
+                    secret,
+                    request.headers.get("X-Hub-Signature"),
+                    body.as_bytes(),
+                ) {
+                    error!("Invalid signature: {e}");
+                    return Err(e).at(Stage::Verify);
+                }
             }
+
+            // A feed may batch several entries
+            let feed = Feed::try_parse_with(body, &context.classifier).at(Stage::Notify)?;
+            let notifications = feed
+                .entries
+                .into_iter()
+                .filter(|notification| {
+                    if !context.forward.contains(&notification.class) {
+                        info!(
+                            "Notification class {:?} not forwarded; pass",
+                            notification.class
+                        );
+                        return false;
+                    }
+                    let Some(dedup) = &context.dedup else {
+                        return true;
+                    };
+                    match dedup.first_delivery(&notification.video_id) {
+                        Ok(true) => true,
+                        Ok(false) => {
+                            info!("Video {} already delivered; pass", notification.video_id);
+                            false
+                        }
+                        // Better deliver twice than not at all
+                        Err(e) => {
+                            error!("Cannot save the deduplication state: {e}");
+                            true
+                        }
+                    }
+                })
+                .map(|mut notification| {
+                    notification.subscription = subscription.clone();
+                    Event::Notification(notification)
+                });
+            let deletions = feed.deleted.into_iter().map(|mut deletion| {
+                deletion.subscription = subscription.clone();
+                Event::Deleted(deletion)
+            });
+
+            notifications.chain(deletions).collect()
         }
         _ => {
             warn!("Unhandled request: {request:#?}");
-
-            None
+            return Err(HandleConnectionError::MethodNotAllowed(method.to_string()))
+                .at(Stage::Parse);
         }
     };
 
-    stream.flush()?;
-
-    Ok(notification)
+    Ok(events)
 }
 
-fn handle_response(response: Response) -> Result<(), Error> {
-    let from = response
-        .headers
-        .get("From")
-        .ok_or_else(|| ParseError::NotFound("From header".to_string()))?;
-    if *from != "googlebot(at)googlebot.com" {
-        error!("unknown source : {from}");
-        return Err(HandleConnectionError::Empty.into());
+fn handle_response(
+    response: Response,
+    peer: Option<SocketAddr>,
+    context: &Context,
+) -> Result<(), StageError> {
+    if let Err(reason) = context.source_policy.check(peer, &response.headers) {
+        error!("Untrusted source: {reason}");
+        return Err(HandleConnectionError::UntrustedSource(reason)).at(Stage::Verify);
     }
     let status_code = response.status_line.status_code;
     let _status_message = response.status_line.status_message;
@@ -271,8 +719,9 @@ fn handle_response(response: Response) -> Result<(), Error> {
         code if code.starts_with('4') || code.starts_with('5') => {
             let reason = response
                 .body
-                .ok_or_else(|| HandleConnectionError::NoBodyError)?;
-            return Err(SubscriptionError(reason.to_string()));
+                .ok_or(HandleConnectionError::NoBodyError)
+                .at(Stage::Parse)?;
+            return Err(SubscriptionError(reason.to_string())).at(Stage::Verify);
         }
         _ => {
             warn!("Unhandled response: {response:#?}");
@@ -7,12 +7,12 @@ pub enum Message<'a> {
 }
 
 impl<'a> Message<'a> {
-    pub(super) fn from_str(message: &'a str) -> Result<Self, Error> {
-        if message.starts_with("HTTP/") {
-            Ok(Self::Response(Response::parse(message)?))
+    /// Parse a message from its head (start line and headers) and body.
+    pub(super) fn parse(head: &'a str, body: &'a str) -> Result<Self, Error> {
+        if head.starts_with("HTTP/") {
+            Ok(Self::Response(Response::parse(head, body)?))
         } else {
-            Ok(Self::Request(Request::try_parse(message)?))
+            Ok(Self::Request(Request::try_parse(head, body)?))
         }
     }
 }
-
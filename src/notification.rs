@@ -1,7 +1,10 @@
-use time::{format_description::well_known::Iso8601, Duration, OffsetDateTime};
+use time::{format_description::well_known::Iso8601, OffsetDateTime};
 
+use crate::classifier::{Class, Classifier};
 use crate::error::NotificationError;
+use crate::parse::{parse_xml, Element, ATOM_NS, TOMBSTONES_NS, YOUTUBE_NS};
 use crate::prelude::Error;
+use crate::subscription::Subscription;
 
 #[derive(Debug)]
 pub struct Notification {
@@ -11,52 +14,234 @@ pub struct Notification {
     pub channel_name: String,
     pub published: OffsetDateTime,
     pub updated: OffsetDateTime,
+    /// Kind of change the notification is about.
+    pub class: Class,
+    /// Atom id of the entry, e.g. `yt:video:{video_id}`.
+    pub entry_id: String,
+    /// Video url, from `<link rel="alternate">`.
+    pub video_url: Option<String>,
+    /// Channel url, from the author `<uri>`.
+    pub author_uri: Option<String>,
+    /// Topic url of the feed, from `<link rel="self">`.
+    pub feed_self_link: Option<String>,
+    /// Hubs the feed is published to, from `<link rel="hub">`.
+    pub feed_hub_links: Vec<String>,
+    /// Whole feed the entry was delivered in.
     pub raw: String,
+    /// Subscription the notification was delivered for, with per-topic callbacks.
+    pub subscription: Option<Subscription>,
+}
+
+/// Feed delivered by the hub, with one notification per entry.
+#[derive(Debug)]
+pub struct Feed {
+    pub title: Option<String>,
+    /// Topic url of the feed, from `<link rel="self">`.
+    pub self_link: Option<String>,
+    /// Hubs the feed is published to, from `<link rel="hub">`.
+    pub hub_links: Vec<String>,
+    pub entries: Vec<Notification>,
+    /// Entries deleted or made private.
+    pub deleted: Vec<Deletion>,
+}
+
+/// Tombstone of a deleted or private video, from `<at:deleted-entry>`.
+#[derive(Debug, Clone)]
+pub struct Deletion {
+    pub video_id: String,
+    pub channel_id: String,
+    pub deleted: OffsetDateTime,
+    /// Whole feed the tombstone was delivered in.
+    pub raw: String,
+    /// Subscription the deletion was delivered for, with per-topic callbacks.
+    pub subscription: Option<Subscription>,
+}
+
+/// Event delivered by the hub.
+#[derive(Debug)]
+pub enum Event {
+    Notification(Notification),
+    Deleted(Deletion),
+}
+
+impl Feed {
+    /// Parse every entry of an Atom feed, classified with the default
+    /// [`Classifier`].
+    pub fn try_parse(xml: &str) -> Result<Self, Error> {
+        Self::try_parse_with(xml, &Classifier::default())
+    }
+
+    /// Parse every entry of an Atom feed, classified with `classifier`.
+    pub fn try_parse_with(xml: &str, classifier: &Classifier) -> Result<Self, Error> {
+        let feed = parse_xml(xml)?;
+        if !feed.is(ATOM_NS, "feed") {
+            return Err(NotificationError::MissingParameter("feed".to_string()).into());
+        }
+
+        let self_link = links(&feed, "self").next().map(str::to_string);
+        let hub_links: Vec<String> = links(&feed, "hub").map(str::to_string).collect();
+
+        Ok(Self {
+            title: feed
+                .child(ATOM_NS, "title")
+                .map(|title| title.text().to_string()),
+            entries: feed
+                .children(ATOM_NS, "entry")
+                .map(|entry| {
+                    Notification::from_entry(entry, &self_link, &hub_links, classifier, xml)
+                })
+                .collect::<Result<_, _>>()?,
+            self_link,
+            hub_links,
+            deleted: feed
+                .children(TOMBSTONES_NS, "deleted-entry")
+                .map(|entry| Deletion::from_deleted_entry(entry, xml))
+                .collect::<Result<_, _>>()?,
+        })
+    }
 }
 
 impl Notification {
+    /// Parse the first entry of an Atom feed.
+    ///
+    /// See [`Feed::try_parse`] to get every entry.
     pub fn try_parse(xml: &str) -> Result<Self, Error> {
-        let parsed = super::parse::parse_xml(xml);
+        Feed::try_parse(xml)?
+            .entries
+            .into_iter()
+            .next()
+            .ok_or_else(|| NotificationError::MissingParameter("entry".to_string()).into())
+    }
+
+    fn from_entry(
+        entry: &Element,
+        feed_self_link: &Option<String>,
+        feed_hub_links: &[String],
+        classifier: &Classifier,
+        xml: &str,
+    ) -> Result<Self, NotificationError> {
+        let author = entry
+            .child(ATOM_NS, "author")
+            .ok_or_else(|| NotificationError::MissingParameter("author".to_string()))?;
+        let published =
+            OffsetDateTime::parse(entry.child_text(ATOM_NS, "published")?, &Iso8601::DEFAULT)?;
+        let updated =
+            OffsetDateTime::parse(entry.child_text(ATOM_NS, "updated")?, &Iso8601::DEFAULT)?;
 
         let video = Notification {
-            video_id: (*parsed
-                .get("yt:videoId")
-                .ok_or_else(|| NotificationError::MissingParameter("yt:videoId".to_string()))?)
-            .to_string(),
-            channel_id: (*parsed
-                .get("yt:channelId")
-                .ok_or_else(|| NotificationError::MissingParameter("yt:channelId".to_string()))?)
-            .to_string(),
-            video_title: (*parsed
-                .get("title")
-                .ok_or_else(|| NotificationError::MissingParameter("title".to_string()))?)
-            .to_string(),
-            channel_name: (*parsed
-                .get("name")
-                .ok_or_else(|| NotificationError::MissingParameter("name".to_string()))?)
-            .to_string(),
-            published: OffsetDateTime::parse(
-                parsed
-                    .get("published")
-                    .ok_or_else(|| NotificationError::MissingParameter("published".to_string()))?,
-                &Iso8601::DEFAULT,
-            )
-            .map_err(NotificationError::DateTimeError)?,
-            updated: OffsetDateTime::parse(
-                parsed
-                    .get("updated")
-                    .ok_or_else(|| NotificationError::MissingParameter("updated".to_string()))?,
-                &Iso8601::DEFAULT,
-            )
-            .map_err(NotificationError::DateTimeError)?,
+            video_id: entry.child_text(YOUTUBE_NS, "videoId")?.to_string(),
+            channel_id: entry.child_text(YOUTUBE_NS, "channelId")?.to_string(),
+            video_title: entry.child_text(ATOM_NS, "title")?.to_string(),
+            channel_name: author.child_text(ATOM_NS, "name")?.to_string(),
+            published,
+            updated,
+            class: classifier.classify(published, updated),
+            entry_id: entry.child_text(ATOM_NS, "id")?.to_string(),
+            video_url: links(entry, "alternate").next().map(str::to_string),
+            author_uri: author
+                .child(ATOM_NS, "uri")
+                .map(|uri| uri.text().to_string()),
+            feed_self_link: feed_self_link.clone(),
+            feed_hub_links: feed_hub_links.to_vec(),
             raw: xml.to_string(),
+            subscription: None,
         };
 
         Ok(video)
     }
 
+    /// Whether the notification is about a new upload, see [`Class`].
     pub fn is_new(&self) -> bool {
-        self.updated - self.published < Duration::minutes(5)
+        self.class == Class::NewUpload
+    }
+
+    /// `https://www.youtube.com/watch?v={video_id}`
+    pub fn watch_url(&self) -> String {
+        format!("https://www.youtube.com/watch?v={}", self.video_id)
+    }
+
+    /// `https://www.youtube.com/channel/{channel_id}`
+    pub fn channel_url(&self) -> String {
+        format!("https://www.youtube.com/channel/{}", self.channel_id)
+    }
+
+    /// Url of one of the standard thumbnails of the video.
+    ///
+    /// Not every video has the [`Thumbnail::Standard`] and
+    /// [`Thumbnail::MaxRes`] thumbnails.
+    pub fn thumbnail_url(&self, thumbnail: Thumbnail) -> String {
+        format!(
+            "https://i.ytimg.com/vi/{}/{}.jpg",
+            self.video_id,
+            thumbnail.file_name()
+        )
+    }
+}
+
+/// Standard thumbnails of a YouTube video.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Thumbnail {
+    /// 120x90
+    Default,
+    /// 320x180
+    Medium,
+    /// 480x360
+    High,
+    /// 640x480
+    Standard,
+    /// 1280x720
+    MaxRes,
+}
+
+impl Thumbnail {
+    fn file_name(self) -> &'static str {
+        match self {
+            Self::Default => "default",
+            Self::Medium => "mqdefault",
+            Self::High => "hqdefault",
+            Self::Standard => "sddefault",
+            Self::MaxRes => "maxresdefault",
+        }
+    }
+}
+
+/// Urls of the Atom `<link>` children of `element` with relation `rel`.
+fn links<'a>(element: &'a Element, rel: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+    element
+        .children(ATOM_NS, "link")
+        .filter(move |link| link.attribute("rel") == Some(rel))
+        .filter_map(|link| link.attribute("href"))
+}
+
+impl Deletion {
+    fn from_deleted_entry(entry: &Element, xml: &str) -> Result<Self, NotificationError> {
+        let reference = entry
+            .attribute("ref")
+            .ok_or_else(|| NotificationError::MissingParameter("ref".to_string()))?;
+        let when = entry
+            .attribute("when")
+            .ok_or_else(|| NotificationError::MissingParameter("when".to_string()))?;
+        // The channel is only given by the url of the author
+        let uri = entry
+            .child(TOMBSTONES_NS, "by")
+            .ok_or_else(|| NotificationError::MissingParameter("by".to_string()))?
+            .child_text(ATOM_NS, "uri")?;
+
+        Ok(Self {
+            video_id: reference
+                .strip_prefix("yt:video:")
+                .unwrap_or(reference)
+                .to_string(),
+            channel_id: uri
+                .trim_end_matches('/')
+                .rsplit('/')
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+            deleted: OffsetDateTime::parse(when, &Iso8601::DEFAULT)?,
+            raw: xml.to_string(),
+            subscription: None,
+        })
     }
 }
 
@@ -69,3 +254,161 @@ impl std::fmt::Display for Notification {
         writeln!(f, "{text}")
     }
 }
+
+impl std::fmt::Display for Deletion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Deleted #id: {}; channel: {}; at {}",
+            self.video_id, self.channel_id, self.deleted
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Notification as sent by the YouTube hub.
+    const YOUTUBE_FEED: &str = r#"<?xml version='1.0' encoding='UTF-8'?>
+<feed xmlns:yt="http://www.youtube.com/xml/schemas/2015" xmlns="http://www.w3.org/2005/Atom"><link rel="hub" href="https://pubsubhubbub.appspot.com"/><link rel="self" href="https://www.youtube.com/xml/feeds/videos.xml?channel_id=UCXuqSBlHAE6Xw-yeJA0Tunw"/><title>YouTube video feed</title><updated>2023-11-14T17:02:11.427373262+00:00</updated><entry>
+  <id>yt:video:dQw4w9WgXcQ</id>
+  <yt:videoId>dQw4w9WgXcQ</yt:videoId>
+  <yt:channelId>UCXuqSBlHAE6Xw-yeJA0Tunw</yt:channelId>
+  <title>Tom &amp; Jerry</title>
+  <link rel="alternate" href="https://www.youtube.com/watch?v=dQw4w9WgXcQ"/>
+  <author>
+   <name>Linus Tech Tips</name>
+   <uri>https://www.youtube.com/channel/UCXuqSBlHAE6Xw-yeJA0Tunw</uri>
+  </author>
+  <published>2023-11-14T17:00:07+00:00</published>
+  <updated>2023-11-14T17:02:11.427373262+00:00</updated>
+ </entry></feed>
+"#;
+
+    fn entry(video_id: &str, title: &str) -> String {
+        format!(
+            "<entry><id>yt:video:{video_id}</id><yt:videoId>{video_id}</yt:videoId><yt:channelId>UC123</yt:channelId><title>{title}</title><author><name>Channel</name></author><published>2023-11-14T17:00:07+00:00</published><updated>2023-11-14T17:02:11+00:00</updated></entry>"
+        )
+    }
+
+    fn feed(content: &str) -> String {
+        format!(
+            r#"<feed xmlns:yt="http://www.youtube.com/xml/schemas/2015" xmlns:at="http://purl.org/atompub/tombstones/1.0" xmlns="http://www.w3.org/2005/Atom">{content}</feed>"#
+        )
+    }
+
+    #[test]
+    fn parses_youtube_feed() {
+        let notification = Notification::try_parse(YOUTUBE_FEED).unwrap();
+        assert_eq!(notification.video_id, "dQw4w9WgXcQ");
+        assert_eq!(notification.channel_id, "UCXuqSBlHAE6Xw-yeJA0Tunw");
+        assert_eq!(notification.video_title, "Tom & Jerry");
+        assert_eq!(notification.channel_name, "Linus Tech Tips");
+        assert_eq!(notification.entry_id, "yt:video:dQw4w9WgXcQ");
+        assert_eq!(notification.published.unix_timestamp(), 1699981207);
+        assert_eq!(notification.updated.unix_timestamp(), 1699981331);
+        assert_eq!(
+            notification.video_url.as_deref(),
+            Some("https://www.youtube.com/watch?v=dQw4w9WgXcQ")
+        );
+        assert_eq!(
+            notification.author_uri.as_deref(),
+            Some("https://www.youtube.com/channel/UCXuqSBlHAE6Xw-yeJA0Tunw")
+        );
+        assert_eq!(
+            notification.feed_self_link.as_deref(),
+            Some(
+                "https://www.youtube.com/xml/feeds/videos.xml?channel_id=UCXuqSBlHAE6Xw-yeJA0Tunw"
+            )
+        );
+        assert_eq!(
+            notification.feed_hub_links,
+            ["https://pubsubhubbub.appspot.com"]
+        );
+        assert_eq!(notification.raw, YOUTUBE_FEED);
+        // Published years ago
+        assert_eq!(notification.class, Class::Resurfaced);
+        assert_eq!(
+            notification.watch_url(),
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ"
+        );
+        assert_eq!(
+            notification.thumbnail_url(Thumbnail::MaxRes),
+            "https://i.ytimg.com/vi/dQw4w9WgXcQ/maxresdefault.jpg"
+        );
+    }
+
+    #[test]
+    fn parses_minified_feed() {
+        let minified: String = YOUTUBE_FEED.lines().map(str::trim).collect();
+        let notification = Notification::try_parse(&minified).unwrap();
+        assert_eq!(notification.video_id, "dQw4w9WgXcQ");
+        assert_eq!(notification.video_title, "Tom & Jerry");
+        assert_eq!(notification.channel_name, "Linus Tech Tips");
+    }
+
+    #[test]
+    fn parses_cdata_title() {
+        let xml = feed(&entry("abc", "<![CDATA[Tom & Jerry <3]]>"));
+        let notification = Notification::try_parse(&xml).unwrap();
+        assert_eq!(notification.video_title, "Tom & Jerry <3");
+    }
+
+    #[test]
+    fn parses_every_entry() {
+        let xml = feed(&format!(
+            "<title>Videos</title>{}{}",
+            entry("first", "First"),
+            entry("second", "Second")
+        ));
+        let feed = Feed::try_parse(&xml).unwrap();
+        assert_eq!(feed.title.as_deref(), Some("Videos"));
+        let ids: Vec<_> = feed
+            .entries
+            .iter()
+            .map(|entry| entry.video_id.as_str())
+            .collect();
+        assert_eq!(ids, ["first", "second"]);
+        assert!(feed.deleted.is_empty());
+
+        // Only the first one is kept
+        assert_eq!(Notification::try_parse(&xml).unwrap().video_id, "first");
+    }
+
+    #[test]
+    fn parses_deleted_entry() {
+        let xml = feed(
+            r#"<at:deleted-entry ref="yt:video:dQw4w9WgXcQ" when="2023-11-14T18:00:00+00:00">
+                <link href="https://www.youtube.com/watch?v=dQw4w9WgXcQ"/>
+                <at:by>
+                    <name>Linus Tech Tips</name>
+                    <uri>https://www.youtube.com/channel/UCXuqSBlHAE6Xw-yeJA0Tunw</uri>
+                </at:by>
+            </at:deleted-entry>"#,
+        );
+        let feed = Feed::try_parse(&xml).unwrap();
+        assert!(feed.entries.is_empty());
+        let [deletion] = feed.deleted.as_slice() else {
+            panic!("expected one deletion, got {:?}", feed.deleted);
+        };
+        assert_eq!(deletion.video_id, "dQw4w9WgXcQ");
+        assert_eq!(deletion.channel_id, "UCXuqSBlHAE6Xw-yeJA0Tunw");
+        assert_eq!(deletion.deleted.unix_timestamp(), 1699984800);
+
+        // There is no entry to notify
+        assert!(Notification::try_parse(&xml).is_err());
+    }
+
+    #[test]
+    fn rejects_incomplete_entries() {
+        let xml = feed(&entry("abc", "Title").replace("<yt:videoId>abc</yt:videoId>", ""));
+        assert!(matches!(
+            Notification::try_parse(&xml),
+            Err(Error::Notification(NotificationError::MissingParameter(name))) if name == "videoId"
+        ));
+
+        let xml = r#"<rss xmlns="http://www.w3.org/2005/Atom"></rss>"#;
+        assert!(Feed::try_parse(xml).is_err());
+    }
+}
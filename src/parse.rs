@@ -1,36 +1,222 @@
-use std::collections::HashMap;
-
-pub(super) fn parse_xml(xml: &str) -> HashMap<&str, &str> {
-    let mut v = HashMap::new();
-    let tags = &[
-        "<yt:videoId>",
-        "<yt:channelId>",
-        "<title>",
-        "<name>",
-        "<published>",
-        "<updated>",
-    ];
-    let mut lines = xml.lines().map(str::trim);
-
-    for &t in tags {
-        let l = lines.find(|l| l.starts_with(t));
-        if let Some(l) = l {
-            // length of tag
-            let tl = t.len();
-            // length of line
-            let ll = l.len();
-
-            // <tag> without brackets
-            let t = &t[1..(tl - 1)];
-            // <tag>value</tag>
-            // value starts at index = tag.len() (here 4)
-            // value ends at index = tag.len - line.len - 1 (-1 because </tag> is one char longer
-            // than <tag>)
-            let l = &l[tl..(ll - tl - 1)];
-
-            v.insert(t, l);
+use quick_xml::{
+    events::{BytesStart, Event},
+    name::ResolveResult,
+    NsReader,
+};
+
+use crate::error::NotificationError;
+
+pub(super) const ATOM_NS: &str = "http://www.w3.org/2005/Atom";
+pub(super) const YOUTUBE_NS: &str = "http://www.youtube.com/xml/schemas/2015";
+pub(super) const TOMBSTONES_NS: &str = "http://purl.org/atompub/tombstones/1.0";
+
+/// XML element with its namespace resolved.
+#[derive(Debug, Default)]
+pub(super) struct Element {
+    pub(super) namespace: Option<String>,
+    pub(super) name: String,
+    /// Attributes as `(namespace, local name, value)`.
+    pub(super) attributes: Vec<(Option<String>, String, String)>,
+    pub(super) children: Vec<Element>,
+    /// Text and CDATA content, with entities decoded.
+    pub(super) text: String,
+}
+
+impl Element {
+    pub(super) fn is(&self, namespace: &str, name: &str) -> bool {
+        self.namespace.as_deref() == Some(namespace) && self.name == name
+    }
+
+    /// First child element `namespace:name`.
+    pub(super) fn child(&self, namespace: &str, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.is(namespace, name))
+    }
+
+    /// Every child element `namespace:name`.
+    pub(super) fn children<'a>(
+        &'a self,
+        namespace: &'a str,
+        name: &'a str,
+    ) -> impl Iterator<Item = &'a Element> + 'a {
+        self.children
+            .iter()
+            .filter(move |child| child.is(namespace, name))
+    }
+
+    /// Value of an attribute without namespace.
+    pub(super) fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(namespace, n, _)| namespace.is_none() && n == name)
+            .map(|(_, _, value)| value.as_str())
+    }
+
+    /// Text content without the surrounding whitespace.
+    pub(super) fn text(&self) -> &str {
+        self.text.trim()
+    }
+
+    /// Text of the first child element `namespace:name`.
+    pub(super) fn child_text(
+        &self,
+        namespace: &str,
+        name: &str,
+    ) -> Result<&str, NotificationError> {
+        self.child(namespace, name)
+            .map(Element::text)
+            .ok_or_else(|| NotificationError::MissingParameter(name.to_string()))
+    }
+}
+
+/// Parse an XML document into its root element.
+pub(super) fn parse_xml(xml: &str) -> Result<Element, NotificationError> {
+    let mut reader = NsReader::from_str(xml);
+    // Opened elements, the last one is the current one
+    let mut stack: Vec<Element> = vec![];
+
+    loop {
+        let (namespace, event) = reader.read_resolved_event()?;
+        let namespace = resolved(namespace)?;
+        match event {
+            Event::Start(start) => {
+                let element = new_element(&reader, namespace, &start)?;
+                stack.push(element);
+            }
+            Event::Empty(start) => {
+                let element = new_element(&reader, namespace, &start)?;
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(element),
+                    None => return Ok(element),
+                }
+            }
+            Event::End(_) => {
+                let element = stack.pop().ok_or_else(|| {
+                    NotificationError::Malformed("unexpected end tag".to_string())
+                })?;
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(element),
+                    None => return Ok(element),
+                }
+            }
+            Event::Text(text) => {
+                if let Some(element) = stack.last_mut() {
+                    element.text.push_str(&text.unescape()?);
+                }
+            }
+            Event::CData(cdata) => {
+                if let Some(element) = stack.last_mut() {
+                    element.text.push_str(&cdata.decode()?);
+                }
+            }
+            Event::Eof => {
+                return Err(NotificationError::Malformed(
+                    "unexpected end of document".to_string(),
+                ))
+            }
+            Event::Decl(_) | Event::PI(_) | Event::Comment(_) | Event::DocType(_) => (),
         }
     }
+}
+
+fn new_element(
+    reader: &NsReader<&[u8]>,
+    namespace: Option<String>,
+    start: &BytesStart,
+) -> Result<Element, NotificationError> {
+    let mut element = Element {
+        namespace,
+        name: String::from_utf8_lossy(start.local_name().as_ref()).into_owned(),
+        ..Default::default()
+    };
+
+    for attribute in start.attributes() {
+        let attribute = attribute.map_err(quick_xml::Error::from)?;
+        let (namespace, name) = reader.resolve_attribute(attribute.key);
+        // Namespace declarations are already resolved
+        if attribute.key.as_namespace_binding().is_some() {
+            continue;
+        }
+        element.attributes.push((
+            resolved(namespace)?,
+            String::from_utf8_lossy(name.as_ref()).into_owned(),
+            attribute.unescape_value()?.into_owned(),
+        ));
+    }
+
+    Ok(element)
+}
+
+fn resolved(namespace: ResolveResult) -> Result<Option<String>, NotificationError> {
+    match namespace {
+        ResolveResult::Bound(namespace) => Ok(Some(
+            String::from_utf8_lossy(namespace.as_ref()).into_owned(),
+        )),
+        ResolveResult::Unbound => Ok(None),
+        ResolveResult::Unknown(prefix) => Err(NotificationError::Malformed(format!(
+            "unknown namespace prefix {}",
+            String::from_utf8_lossy(&prefix)
+        ))),
+    }
+}
 
-    v
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_namespaces() {
+        let xml = r#"<feed xmlns:yt="http://www.youtube.com/xml/schemas/2015" xmlns="http://www.w3.org/2005/Atom">
+            <entry><yt:videoId>abc</yt:videoId><link rel="alternate" href="https://example.com"/></entry>
+        </feed>"#;
+        let feed = parse_xml(xml).unwrap();
+        assert!(feed.is(ATOM_NS, "feed"));
+        // The namespace declarations are not kept as attributes
+        assert!(feed.attributes.is_empty());
+
+        let entry = feed.child(ATOM_NS, "entry").unwrap();
+        assert_eq!(entry.child_text(YOUTUBE_NS, "videoId").unwrap(), "abc");
+        assert!(entry.child(ATOM_NS, "videoId").is_none());
+        let link = entry.child(ATOM_NS, "link").unwrap();
+        assert_eq!(link.attribute("rel"), Some("alternate"));
+        assert_eq!(link.attribute("href"), Some("https://example.com"));
+    }
+
+    #[test]
+    fn decodes_entities_and_cdata() {
+        let xml = r#"<feed xmlns="http://www.w3.org/2005/Atom">
+            <title> Tom &amp; Jerry </title>
+            <subtitle><![CDATA[<b>Tom</b> & Jerry]]></subtitle>
+            <link title="&quot;Tom&quot;"/>
+        </feed>"#;
+        let feed = parse_xml(xml).unwrap();
+        assert_eq!(feed.child_text(ATOM_NS, "title").unwrap(), "Tom & Jerry");
+        assert_eq!(
+            feed.child_text(ATOM_NS, "subtitle").unwrap(),
+            "<b>Tom</b> & Jerry"
+        );
+        let link = feed.child(ATOM_NS, "link").unwrap();
+        assert_eq!(link.attribute("title"), Some("\"Tom\""));
+    }
+
+    #[test]
+    fn missing_child_is_an_error() {
+        let feed = parse_xml(r#"<feed xmlns="http://www.w3.org/2005/Atom"/>"#).unwrap();
+        assert!(matches!(
+            feed.child_text(ATOM_NS, "title"),
+            Err(NotificationError::MissingParameter(name)) if name == "title"
+        ));
+    }
+
+    #[test]
+    fn rejects_malformed_documents() {
+        assert!(matches!(
+            parse_xml("<feed><entry></entry>"),
+            Err(NotificationError::Malformed(_))
+        ));
+        assert!(matches!(
+            parse_xml("<yt:videoId>abc</yt:videoId>"),
+            Err(NotificationError::Malformed(_))
+        ));
+        assert!(parse_xml("").is_err());
+    }
 }
@@ -1,4 +1,15 @@
-pub use crate::error::Error;
-pub use crate::notification::Notification;
+#[cfg(feature = "tokio")]
+pub use crate::async_listener::EventStream;
+pub use crate::classifier::{Class, Classifier};
+pub use crate::dedup::DedupConfig;
+pub use crate::error::{ConnectionError, Error, Stage, StorageError};
+pub use crate::handle::ListenerHandle;
+pub use crate::handler::{handler_fn, Events, Handler, HandlerFn};
+pub use crate::hub::{RetryAfter, SubscribeOutcome};
+pub use crate::notification::{Deletion, Event, Feed, Notification, Thumbnail};
+pub use crate::registry::{Counts, Registration, Registry, SubscriptionState};
+pub use crate::source::{Cidr, Source, SourcePolicy};
+pub use crate::storage::{FileStorage, MemoryStorage, Storage};
+pub use crate::subscription::{Lease, Subscription, Verification};
 pub use crate::HookListener;
 pub use crate::Mode;
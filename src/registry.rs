@@ -0,0 +1,636 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use time::OffsetDateTime;
+use tracing::error;
+
+use crate::error::StorageError;
+use crate::storage::Storage;
+use crate::subscription::{Lease, MAX_LEASE};
+use crate::Mode;
+
+/// State of the subscription to a topic.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubscriptionState {
+    /// Subscription requested, waiting for the verification of intent.
+    Pending,
+    /// Subscription verified by the hub.
+    Active {
+        /// When the lease expires, if the hub granted one.
+        expires_at: Option<OffsetDateTime>,
+    },
+    /// Unsubscription verified by the hub, or requested for a topic the
+    /// listener did not know.
+    Unsubscribed,
+    /// Subscription refused by the hub.
+    Denied { reason: String },
+    /// Subscription request redirected to another hub.
+    Redirected {
+        status: u16,
+        location: Option<String>,
+    },
+}
+
+/// Why a request was not accepted by the hub.
+#[derive(Debug)]
+pub(crate) enum Failure {
+    /// The hub could not be reached.
+    Unreachable,
+    Denied(String),
+    /// The hub is temporarily unavailable, retry at the given time.
+    Retry(OffsetDateTime),
+    Redirected {
+        status: u16,
+        location: Option<String>,
+    },
+}
+
+/// Subscription to a topic tracked by the listener.
+#[derive(Debug, Clone)]
+pub struct Registration {
+    pub topic: String,
+    pub state: SubscriptionState,
+    /// Request sent to the hub and waiting for its verification of intent,
+    /// e.g. the renewal of an active subscription.
+    pub pending: Option<Mode>,
+    /// Id of the callback given to the hub, with per-topic callbacks.
+    pub callback_id: Option<String>,
+    pub lease: Lease,
+}
+
+impl Registration {
+    fn new(topic: &str, mode: Mode) -> Self {
+        let state = match mode {
+            Mode::Subscribe => SubscriptionState::Pending,
+            Mode::Unsubscribe => SubscriptionState::Unsubscribed,
+        };
+        Self {
+            topic: topic.to_string(),
+            state,
+            pending: None,
+            callback_id: None,
+            lease: Lease::new(topic),
+        }
+    }
+
+    /// Id of the YouTube channel, for the topic of a channel's videos feed.
+    pub fn channel_id(&self) -> Option<&str> {
+        let (_, query) = self.topic.split_once('?')?;
+        query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("channel_id="))
+    }
+}
+
+/// Number of subscriptions in each state.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counts {
+    pub pending: usize,
+    pub active: usize,
+    pub unsubscribed: usize,
+    pub denied: usize,
+    pub redirected: usize,
+}
+
+type Registrations = HashMap<String, Registration>;
+
+/// Subscriptions of the listener, keyed by topic url.
+///
+/// Updated by the subscription requests, the verifications of intent and
+/// the denials sent by the hub, and saved to the storage on each change.
+#[derive(Default)]
+pub struct Registry {
+    registrations: Mutex<Registrations>,
+    storage: Option<Arc<dyn Storage>>,
+}
+
+impl fmt::Debug for Registry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Registry")
+            .field("registrations", &self.registrations)
+            .field("storage", &self.storage.as_ref().map(|_| "Storage"))
+            .finish()
+    }
+}
+
+impl Registry {
+    /// Create the registry, loading the subscriptions saved to `storage`.
+    ///
+    /// The renewals of the active subscriptions resume as scheduled, and
+    /// the new subscriptions still waiting for their verification are
+    /// requested again at the first renewal.
+    pub(crate) fn load(storage: Arc<dyn Storage>) -> Result<Self, StorageError> {
+        let now = OffsetDateTime::now_utc();
+        let registrations = storage
+            .load()?
+            .into_iter()
+            .map(|mut registration| {
+                if registration.state == SubscriptionState::Pending {
+                    match registration.pending {
+                        Some(Mode::Subscribe) => {
+                            registration.lease.renew_at.get_or_insert(now);
+                        }
+                        // Unsubscription of an unknown topic, saved as pending
+                        // by previous versions
+                        Some(Mode::Unsubscribe) => {
+                            registration.state = SubscriptionState::Unsubscribed;
+                            registration.lease.renew_at = None;
+                        }
+                        None => (),
+                    }
+                }
+                (registration.topic.clone(), registration)
+            })
+            .collect();
+
+        Ok(Self {
+            registrations: Mutex::new(registrations),
+            storage: Some(storage),
+        })
+    }
+
+    /// Get the subscription to `topic`.
+    pub fn get(&self, topic: &str) -> Option<Registration> {
+        self.registrations.lock().unwrap().get(topic).cloned()
+    }
+
+    /// Get the subscription to the videos feed of the channel `id`.
+    pub fn channel(&self, id: &str) -> Option<Registration> {
+        self.registrations
+            .lock()
+            .unwrap()
+            .values()
+            .find(|registration| registration.channel_id() == Some(id))
+            .cloned()
+    }
+
+    /// List every subscription, in no particular order.
+    pub fn list(&self) -> Vec<Registration> {
+        self.registrations
+            .lock()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    pub fn counts(&self) -> Counts {
+        let mut counts = Counts::default();
+        for registration in self.registrations.lock().unwrap().values() {
+            match registration.state {
+                SubscriptionState::Pending => counts.pending += 1,
+                SubscriptionState::Active { .. } => counts.active += 1,
+                SubscriptionState::Unsubscribed => counts.unsubscribed += 1,
+                SubscriptionState::Denied { .. } => counts.denied += 1,
+                SubscriptionState::Redirected { .. } => counts.redirected += 1,
+            }
+        }
+        counts
+    }
+
+    /// Record a request about to be sent to the hub, replacing any previous
+    /// one for `topic`.
+    pub(crate) fn requested(
+        &self,
+        topic: &str,
+        mode: Mode,
+        lease: Option<Duration>,
+        callback_id: Option<String>,
+    ) {
+        let mut registrations = self.registrations.lock().unwrap();
+        let registration = registrations
+            .entry(topic.to_string())
+            .or_insert_with(|| Registration::new(topic, mode));
+
+        registration.pending = Some(mode);
+        registration.callback_id = callback_id;
+        if mode == Mode::Subscribe {
+            registration.lease.requested = lease;
+            if !matches!(registration.state, SubscriptionState::Active { .. }) {
+                registration.state = SubscriptionState::Pending;
+            }
+        }
+        self.save(&registrations);
+    }
+
+    /// Record a request the hub did not accept.
+    ///
+    /// A subscription the hub is temporarily unable to take is requested
+    /// again at the retry time. Otherwise a new subscription ends denied or
+    /// redirected, and is forgotten if the hub could not be reached.
+    pub(crate) fn failed(&self, topic: &str, mode: Mode, failure: Failure) {
+        let mut registrations = self.registrations.lock().unwrap();
+        let Some(registration) = registrations.get_mut(topic) else {
+            return;
+        };
+        if registration.pending == Some(mode) {
+            registration.pending = None;
+        }
+
+        // An active subscription, or one with another request pending, does
+        // not end with this request
+        let is_new =
+            registration.state == SubscriptionState::Pending && registration.pending.is_none();
+        match failure {
+            Failure::Retry(at) if mode == Mode::Subscribe => {
+                registration.lease.renew_at = Some(at);
+            }
+            Failure::Retry(_) => (),
+            _ if !is_new => (),
+            Failure::Unreachable => {
+                registrations.remove(topic);
+            }
+            Failure::Denied(reason) => {
+                registration.state = SubscriptionState::Denied { reason };
+                registration.lease.renew_at = None;
+            }
+            Failure::Redirected { status, location } => {
+                registration.state = SubscriptionState::Redirected { status, location };
+                registration.lease.renew_at = None;
+            }
+        }
+        self.save(&registrations);
+    }
+
+    /// Whether a request to `topic` in `mode` is waiting for its verification.
+    pub(crate) fn is_pending(&self, topic: &str, mode: Mode) -> bool {
+        self.registrations
+            .lock()
+            .unwrap()
+            .get(topic)
+            .is_some_and(|registration| registration.pending == Some(mode))
+    }
+
+    /// Remove the pending request to `topic` if it matches `mode`.
+    ///
+    /// Returns whether such a request was pending.
+    pub(crate) fn take_pending(&self, topic: &str, mode: Mode) -> bool {
+        let mut registrations = self.registrations.lock().unwrap();
+        match registrations.get_mut(topic) {
+            Some(registration) if registration.pending == Some(mode) => {
+                registration.pending = None;
+                self.save(&registrations);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Record a verified subscription with the lease granted by the hub,
+    /// and schedule its renewal.
+    ///
+    /// The renewal happens between 80% and 90% of the lease duration so
+    /// subscriptions made at the same time are not all renewed at once.
+    pub(crate) fn activate(&self, topic: &str, granted: Option<Duration>) {
+        let now = OffsetDateTime::now_utc();
+        let granted = granted.map(|granted| granted.min(MAX_LEASE));
+
+        let mut registrations = self.registrations.lock().unwrap();
+        let registration = registrations
+            .entry(topic.to_string())
+            .or_insert_with(|| Registration::new(topic, Mode::Subscribe));
+        let lease = &mut registration.lease;
+        lease.granted = granted;
+        lease.expires_at = granted.map(|granted| now + granted);
+        lease.renew_at = granted.map(|granted| {
+            let jitter = granted.mul_f64(fastrand::f64() / 10.0);
+            now + granted.mul_f64(0.8) + jitter
+        });
+        registration.state = SubscriptionState::Active {
+            expires_at: lease.expires_at,
+        };
+        self.save(&registrations);
+    }
+
+    /// Record a verified unsubscription.
+    pub(crate) fn unsubscribed(&self, topic: &str) {
+        self.end(topic, SubscriptionState::Unsubscribed);
+    }
+
+    /// Record a subscription denied by the hub.
+    pub(crate) fn denied(&self, topic: &str, reason: &str) {
+        self.end(
+            topic,
+            SubscriptionState::Denied {
+                reason: reason.to_string(),
+            },
+        );
+    }
+
+    fn end(&self, topic: &str, state: SubscriptionState) {
+        let mut registrations = self.registrations.lock().unwrap();
+        let Some(registration) = registrations.get_mut(topic) else {
+            return;
+        };
+        registration.state = state;
+        registration.pending = None;
+        registration.lease.expires_at = None;
+        registration.lease.renew_at = None;
+        self.save(&registrations);
+    }
+
+    /// Whether the subscription to `topic` is not active anymore: its lease
+    /// expired without being renewed, or it ended.
+    pub(crate) fn is_expired(&self, topic: &str) -> bool {
+        let now = OffsetDateTime::now_utc();
+        self.registrations.lock().unwrap().get(topic).is_some_and(
+            |registration| match &registration.state {
+                SubscriptionState::Active { expires_at } => {
+                    expires_at.is_some_and(|expires_at| expires_at <= now)
+                }
+                SubscriptionState::Pending => false,
+                SubscriptionState::Unsubscribed
+                | SubscriptionState::Denied { .. }
+                | SubscriptionState::Redirected { .. } => registration.pending.is_none(),
+            },
+        )
+    }
+
+    /// Topics subscribed to, or being subscribed to.
+    pub(crate) fn subscribed(&self) -> Vec<String> {
+        self.registrations
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|registration| {
+                matches!(
+                    registration.state,
+                    SubscriptionState::Pending | SubscriptionState::Active { .. }
+                )
+            })
+            .map(|registration| registration.topic.clone())
+            .collect()
+    }
+
+    /// Take the leases due for renewal, except the ones being unsubscribed.
+    ///
+    /// Their renewal is postponed by `retry` in case the new subscription
+    /// request is never verified.
+    pub(crate) fn take_due(&self, retry: Duration) -> Vec<Lease> {
+        let now = OffsetDateTime::now_utc();
+        let mut registrations = self.registrations.lock().unwrap();
+
+        let due: Vec<_> = registrations
+            .values_mut()
+            .filter(|registration| {
+                registration.pending != Some(Mode::Unsubscribe)
+                    && registration
+                        .lease
+                        .renew_at
+                        .is_some_and(|renew_at| renew_at <= now)
+            })
+            .map(|registration| {
+                registration.lease.renew_at = Some(now + retry);
+                registration.lease.clone()
+            })
+            .collect();
+        if !due.is_empty() {
+            self.save(&registrations);
+        }
+        due
+    }
+
+    /// Save the subscriptions, called with the lock held so the saves happen
+    /// in the order of the changes.
+    fn save(&self, registrations: &Registrations) {
+        let Some(storage) = &self.storage else {
+            return;
+        };
+        let mut registrations: Vec<_> = registrations.values().cloned().collect();
+        registrations.sort_by(|a, b| a.topic.cmp(&b.topic));
+        if let Err(e) = storage.save(&registrations) {
+            error!("Cannot save the subscription state: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    const TOPIC: &str = "https://www.youtube.com/xml/feeds/videos.xml?channel_id=UC123";
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    /// Registry with an active subscription to [`TOPIC`].
+    fn active() -> Registry {
+        let registry = Registry::default();
+        registry.requested(TOPIC, Mode::Subscribe, Some(DAY), None);
+        assert!(registry.take_pending(TOPIC, Mode::Subscribe));
+        registry.activate(TOPIC, Some(DAY));
+        registry
+    }
+
+    #[test]
+    fn subscribes_and_unsubscribes() {
+        let registry = Registry::default();
+        registry.requested(TOPIC, Mode::Subscribe, Some(DAY), Some("id".to_string()));
+        let registration = registry.get(TOPIC).unwrap();
+        assert_eq!(registration.state, SubscriptionState::Pending);
+        assert_eq!(registration.pending, Some(Mode::Subscribe));
+        assert_eq!(registration.callback_id.as_deref(), Some("id"));
+        assert_eq!(registration.lease.requested, Some(DAY));
+        assert_eq!(registration.channel_id(), Some("UC123"));
+        assert!(registry.is_pending(TOPIC, Mode::Subscribe));
+        assert!(!registry.is_pending(TOPIC, Mode::Unsubscribe));
+        assert_eq!(registry.subscribed(), [TOPIC]);
+
+        assert!(registry.take_pending(TOPIC, Mode::Subscribe));
+        assert!(!registry.take_pending(TOPIC, Mode::Subscribe));
+        registry.activate(TOPIC, Some(DAY));
+        let registration = registry.channel("UC123").unwrap();
+        let lease = &registration.lease;
+        assert_eq!(
+            registration.state,
+            SubscriptionState::Active {
+                expires_at: lease.expires_at
+            }
+        );
+        assert_eq!(lease.granted, Some(DAY));
+        let (expires_at, renew_at) = (lease.expires_at.unwrap(), lease.renew_at.unwrap());
+        let now = OffsetDateTime::now_utc();
+        assert!(renew_at >= now + DAY.mul_f64(0.79) && renew_at <= now + DAY.mul_f64(0.9));
+        assert!(expires_at > renew_at);
+        assert!(!registry.is_expired(TOPIC));
+
+        registry.requested(TOPIC, Mode::Unsubscribe, None, None);
+        // Still active until the hub verifies the unsubscription
+        assert!(matches!(
+            registry.get(TOPIC).unwrap().state,
+            SubscriptionState::Active { .. }
+        ));
+        assert!(registry.take_pending(TOPIC, Mode::Unsubscribe));
+        registry.unsubscribed(TOPIC);
+        let registration = registry.get(TOPIC).unwrap();
+        assert_eq!(registration.state, SubscriptionState::Unsubscribed);
+        assert_eq!(registration.lease.renew_at, None);
+        assert!(registry.is_expired(TOPIC));
+        assert!(registry.subscribed().is_empty());
+        assert_eq!(
+            registry.counts(),
+            Counts {
+                unsubscribed: 1,
+                ..Counts::default()
+            }
+        );
+    }
+
+    #[test]
+    fn clamps_huge_leases() {
+        let registry = Registry::default();
+        registry.activate(TOPIC, Some(Duration::MAX));
+        assert_eq!(registry.get(TOPIC).unwrap().lease.granted, Some(MAX_LEASE));
+    }
+
+    #[test]
+    fn unsubscribing_unknown_topic_is_not_pending() {
+        let registry = Registry::default();
+        registry.requested(TOPIC, Mode::Unsubscribe, None, None);
+        let registration = registry.get(TOPIC).unwrap();
+        assert_eq!(registration.state, SubscriptionState::Unsubscribed);
+        assert!(registry.subscribed().is_empty());
+    }
+
+    #[test]
+    fn records_denials() {
+        let registry = Registry::default();
+        registry.requested(TOPIC, Mode::Subscribe, None, None);
+        registry.denied(TOPIC, "Unknown topic");
+        let registration = registry.get(TOPIC).unwrap();
+        assert_eq!(
+            registration.state,
+            SubscriptionState::Denied {
+                reason: "Unknown topic".to_string()
+            }
+        );
+        assert_eq!(registration.pending, None);
+        assert!(registry.is_expired(TOPIC));
+
+        // Unknown topics are not recorded
+        registry.denied("https://example.com/feed", "Unknown topic");
+        assert_eq!(registry.list().len(), 1);
+    }
+
+    #[test]
+    fn records_failed_requests() {
+        let registry = Registry::default();
+        registry.requested(TOPIC, Mode::Subscribe, None, None);
+        registry.failed(
+            TOPIC,
+            Mode::Subscribe,
+            Failure::Redirected {
+                status: 307,
+                location: Some("https://example.com/hub".to_string()),
+            },
+        );
+        assert_eq!(
+            registry.get(TOPIC).unwrap().state,
+            SubscriptionState::Redirected {
+                status: 307,
+                location: Some("https://example.com/hub".to_string())
+            }
+        );
+        assert_eq!(registry.counts().redirected, 1);
+
+        registry.requested(TOPIC, Mode::Subscribe, None, None);
+        registry.failed(
+            TOPIC,
+            Mode::Subscribe,
+            Failure::Denied("Refused".to_string()),
+        );
+        assert_eq!(
+            registry.get(TOPIC).unwrap().state,
+            SubscriptionState::Denied {
+                reason: "Refused".to_string()
+            }
+        );
+
+        // A new subscription to an unreachable hub is forgotten
+        registry.requested(TOPIC, Mode::Subscribe, None, None);
+        registry.failed(TOPIC, Mode::Subscribe, Failure::Unreachable);
+        assert!(registry.get(TOPIC).is_none());
+    }
+
+    #[test]
+    fn failed_renewal_keeps_active_subscription() {
+        let registry = active();
+        let expires_at = registry.get(TOPIC).unwrap().lease.expires_at;
+
+        registry.requested(TOPIC, Mode::Subscribe, Some(DAY), None);
+        registry.failed(
+            TOPIC,
+            Mode::Subscribe,
+            Failure::Denied("Refused".to_string()),
+        );
+        let registration = registry.get(TOPIC).unwrap();
+        assert_eq!(registration.state, SubscriptionState::Active { expires_at });
+        assert_eq!(registration.pending, None);
+
+        let retry_at = OffsetDateTime::now_utc() + DAY;
+        registry.requested(TOPIC, Mode::Subscribe, Some(DAY), None);
+        registry.failed(TOPIC, Mode::Subscribe, Failure::Retry(retry_at));
+        let registration = registry.get(TOPIC).unwrap();
+        assert_eq!(registration.state, SubscriptionState::Active { expires_at });
+        assert_eq!(registration.lease.renew_at, Some(retry_at));
+    }
+
+    #[test]
+    fn takes_due_leases() {
+        let registry = active();
+        assert!(registry.take_due(DAY).is_empty());
+
+        let now = OffsetDateTime::now_utc();
+        registry.failed(TOPIC, Mode::Subscribe, Failure::Retry(now));
+        let due = registry.take_due(DAY);
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].topic, TOPIC);
+        // Postponed in case the renewal is never verified
+        let renew_at = registry.get(TOPIC).unwrap().lease.renew_at.unwrap();
+        assert!(renew_at >= now + DAY);
+        assert!(registry.take_due(DAY).is_empty());
+
+        // Not renewed while being unsubscribed
+        registry.failed(TOPIC, Mode::Subscribe, Failure::Retry(now));
+        registry.requested(TOPIC, Mode::Unsubscribe, None, None);
+        assert!(registry.take_due(DAY).is_empty());
+    }
+
+    #[test]
+    fn loads_saved_subscriptions() {
+        let storage = Arc::new(MemoryStorage::new());
+        let registry = Registry::load(storage.clone()).unwrap();
+        registry.requested(TOPIC, Mode::Subscribe, None, None);
+        registry.requested("https://example.com/active", Mode::Subscribe, None, None);
+        registry.activate("https://example.com/active", Some(DAY));
+
+        let mut saved = storage.load().unwrap();
+        assert_eq!(saved.len(), 2);
+        // Unsubscription of an unknown topic saved by previous versions
+        let mut legacy = Registration::new("https://example.com/legacy", Mode::Subscribe);
+        legacy.pending = Some(Mode::Unsubscribe);
+        saved.push(legacy);
+        storage.save(&saved).unwrap();
+
+        let registry = Registry::load(storage).unwrap();
+        let due: Vec<_> = registry
+            .take_due(DAY)
+            .into_iter()
+            .map(|lease| lease.topic)
+            .collect();
+        // Only the subscription still waiting for its verification is
+        // requested again
+        assert_eq!(due, [TOPIC]);
+        assert!(matches!(
+            registry.get("https://example.com/active").unwrap().state,
+            SubscriptionState::Active { .. }
+        ));
+        assert_eq!(
+            registry.get("https://example.com/legacy").unwrap().state,
+            SubscriptionState::Unsubscribed
+        );
+    }
+}
@@ -0,0 +1,100 @@
+use std::fmt;
+
+use crate::error::{Error, FramingError, HandleConnectionError, ParseError, Stage, StageError};
+use crate::notification::Event;
+
+/// Response sent back to the peer of a connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Reply {
+    status: u16,
+    /// Methods accepted on the route, for `405 Method Not Allowed`.
+    allow: Option<&'static str>,
+    body: String,
+}
+
+impl Reply {
+    pub(crate) fn new(status: u16) -> Self {
+        Self {
+            status,
+            allow: None,
+            body: String::new(),
+        }
+    }
+
+    pub(crate) fn with_body(mut self, body: impl Into<String>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Reply to a handled message: `200 OK`, or the status matching the
+    /// error.
+    ///
+    /// No reply is sent when the connection failed, the peer would not
+    /// receive it.
+    pub(crate) fn for_result(result: &Result<Vec<Event>, StageError>) -> Option<Self> {
+        let Err(e) = result else {
+            return Some(Self::new(200));
+        };
+
+        let status = match (e.stage, &e.error) {
+            (Stage::Reply, _)
+            | (Stage::Read, Error::TcpError(_))
+            | (Stage::Read, Error::Framing(FramingError::Io(_) | FramingError::UnexpectedEof)) => {
+                return None
+            }
+            (Stage::Read, Error::Framing(FramingError::BodyTooLarge)) => 413,
+            (Stage::Read, Error::Framing(_)) => 400,
+            (Stage::Parse, Error::HandleConnection(HandleConnectionError::MethodNotAllowed(_))) => {
+                return Some(Self {
+                    allow: Some("GET, POST"),
+                    ..Self::new(405)
+                })
+            }
+            (Stage::Parse, _) => 400,
+            // Unknown route, or verification of intent the subscriber does
+            // not agree with
+            (Stage::Verify, Error::Parse(ParseError::UriError) | Error::SubscriptionError(_)) => {
+                404
+            }
+            // Unknown source or invalid signature
+            (Stage::Verify, _) => 403,
+            (Stage::Notify, Error::Notification(_)) => 400,
+            _ => 500,
+        };
+
+        Some(Self::new(status))
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            413 => "Content Too Large",
+            _ => "Internal Server Error",
+        }
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut head = format!("HTTP/1.1 {} {}\r\n", self.status, self.reason());
+        if let Some(allow) = self.allow {
+            head.push_str(&format!("Allow: {allow}\r\n"));
+        }
+        head.push_str(&format!(
+            "Content-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            self.body.len()
+        ));
+
+        let mut bytes = head.into_bytes();
+        bytes.extend_from_slice(self.body.as_bytes());
+        bytes
+    }
+}
+
+impl fmt::Display for Reply {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.status, self.reason())
+    }
+}
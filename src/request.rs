@@ -1,38 +1,33 @@
 use std::{
     collections::HashMap,
     fmt::{self},
-    path::Path,
 };
 
 use crate::error::ParseError;
+use crate::headers::Headers;
 
 #[derive(Debug)]
 pub(super) struct RequestLine<'a> {
     pub(super) method: &'a str,
-    pub(super) path: &'a Path,
-    pub(super) params: Option<HashMap<&'a str, &'a str>>,
+    pub(super) path: &'a str,
+    /// Query parameters, percent-decoded.
+    pub(super) params: Option<HashMap<String, String>>,
     pub(super) http_version: &'a str,
 }
 
 impl<'a> fmt::Display for RequestLine<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let params = if let Some(map) = &self.params {
-            let mut params = String::from("?");
-            for (k, v) in map {
-                params.push_str(&format!("{k}={v}&"));
-            }
-            params.pop();
-            params
+            let mut params = form_urlencoded::Serializer::new(String::from("?"));
+            params.extend_pairs(map);
+            params.finish()
         } else {
             String::new()
         };
         write!(
             f,
             "{} {}{} {}\r\n",
-            self.method,
-            self.path.display(),
-            params,
-            self.http_version
+            self.method, self.path, params, self.http_version
         )
     }
 }
@@ -41,16 +36,16 @@ impl<'a> fmt::Display for RequestLine<'a> {
 #[derive(Debug)]
 pub(super) struct Request<'a> {
     pub(super) request_line: RequestLine<'a>,
-    pub(super) headers: HashMap<&'a str, &'a str>,
+    pub(super) headers: Headers,
     pub(super) body: Option<&'a str>,
 }
 
 impl<'a> Request<'a> {
-    pub(super) fn try_parse(request: &'a str) -> Result<Self, ParseError> {
+    pub(super) fn try_parse(head: &'a str, body: &'a str) -> Result<Self, ParseError> {
         let mut request_line = None;
-        let mut headers = HashMap::new();
+        let mut headers = Headers::default();
 
-        for (i, line) in request.lines().enumerate() {
+        for (i, line) in head.lines().enumerate() {
             if !line.is_empty() {
                 if i == 0 {
                     request_line = Some(parse_request_line(line)?);
@@ -65,8 +60,7 @@ impl<'a> Request<'a> {
             }
         }
 
-        let empty_line = request.find("\r\n\r\n");
-        let body = empty_line.map(|i| &request[(i + 1)..]);
+        let body = (!body.is_empty()).then_some(body);
 
         Ok(Self {
             request_line: request_line
@@ -77,7 +71,7 @@ impl<'a> Request<'a> {
     }
 }
 
-pub(super) fn parse_request_line(request_line: &str) -> Result<RequestLine, ParseError> {
+pub(super) fn parse_request_line(request_line: &str) -> Result<RequestLine<'_>, ParseError> {
     let mut parts = request_line.split_whitespace();
 
     let method = parts
@@ -94,29 +88,24 @@ pub(super) fn parse_request_line(request_line: &str) -> Result<RequestLine, Pars
                 ParseError::ParameterError("No parameters in request".to_string())
             })?;
 
-            let params_vec = params_string.split('&');
-            let mut params = HashMap::new();
-            for p in params_vec {
-                let (key, value) = p.split_once('=').ok_or_else(|| {
-                    ParseError::ParameterError("Parameter found is not key=value".to_string())
-                })?;
-                params.insert(key, value);
+            if params_string
+                .split('&')
+                .any(|p| !p.is_empty() && !p.contains('='))
+            {
+                return Err(ParseError::ParameterError(
+                    "Parameter found is not key=value".to_string(),
+                ));
             }
+            let params = form_urlencoded::parse(params_string.as_bytes())
+                .into_owned()
+                .collect();
 
-            (Path::new(path), Some(params))
+            (path, Some(params))
         } else {
-            (Path::new(uri), None)
+            (uri, None)
         }
     };
 
-    let norm_uri = path.to_str().expect("Invalid unicode!");
-
-    const ROOT: &str = ".";
-
-    if !Path::new(&format!("{ROOT}{norm_uri}")).exists() {
-        Err(ParseError::UriError)?;
-    }
-
     let http_version = parts
         .next()
         .ok_or_else(|| ParseError::NotFound("HTTP version".to_string()))?;
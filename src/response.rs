@@ -1,7 +1,8 @@
 #![allow(unused)]
-use std::{collections::HashMap, fmt};
+use std::fmt;
 
 use crate::error::ParseError;
+use crate::headers::Headers;
 
 #[derive(Debug)]
 pub(super) struct ResponseLine<'a> {
@@ -13,7 +14,7 @@ pub(super) struct ResponseLine<'a> {
 #[derive(Debug)]
 pub(super) struct Response<'a> {
     pub(super) status_line: ResponseLine<'a>,
-    pub(super) headers: HashMap<&'a str, &'a str>,
+    pub(super) headers: Headers,
     pub(super) body: Option<&'a str>,
 }
 
@@ -30,18 +31,19 @@ impl<'a> fmt::Display for Response<'a> {
 }
 
 impl<'a> Response<'a> {
-    pub(super) fn parse(response: &'a str) -> Result<Self, ParseError> {
+    pub(super) fn parse(head: &'a str, body: &'a str) -> Result<Self, ParseError> {
         let mut http_version = None;
         let mut status_code = None;
         let mut status_message = None;
-        let mut headers = HashMap::new();
-        for (i, line) in response.lines().enumerate() {
+        let mut headers = Headers::default();
+        for (i, line) in head.lines().enumerate() {
             if !line.is_empty() {
                 if i == 0 {
-                    let mut status_line = line.split_whitespace();
+                    // The status message may contain spaces, or be empty
+                    let mut status_line = line.splitn(3, ' ');
                     http_version = status_line.next();
                     status_code = status_line.next();
-                    status_message = status_line.next();
+                    status_message = Some(status_line.next().unwrap_or_default());
                 } else {
                     let (key, value) = line
                         .split_once(": ")
@@ -57,12 +59,10 @@ impl<'a> Response<'a> {
                 .ok_or_else(|| ParseError::NotFound("HTTP version".to_string()))?,
             status_code: status_code
                 .ok_or_else(|| ParseError::NotFound("Status code".to_string()))?,
-            status_message: status_message
-                .ok_or_else(|| ParseError::NotFound("Status message".to_string()))?,
+            status_message: status_message.unwrap_or_default(),
         };
 
-        let empty_line = response.find("\r\n\r\n");
-        let body = empty_line.map(|i| &response[(i + 1)..]);
+        let body = (!body.is_empty()).then_some(body);
 
         Ok(Self {
             status_line,
@@ -0,0 +1,142 @@
+use hmac::{digest::KeyInit, Hmac, Mac};
+use sha1::Sha1;
+use sha2::{Sha256, Sha384, Sha512};
+
+use crate::error::SignatureError;
+
+/// Check the `X-Hub-Signature` header value against the HMAC of `body`.
+///
+/// The header has the form `method=signature` where method is one of
+/// `sha1`, `sha256`, `sha384` or `sha512` and signature is the hex encoded
+/// HMAC of the body, keyed with the `hub.secret` sent when subscribing.
+///
+/// The digests are compared in constant time.
+pub(super) fn verify(
+    secret: &str,
+    header: Option<&str>,
+    body: &[u8],
+) -> Result<(), SignatureError> {
+    let header = header.ok_or(SignatureError::Missing)?;
+    let (method, signature) = header
+        .trim()
+        .split_once('=')
+        .ok_or_else(|| SignatureError::Malformed(header.to_string()))?;
+    let signature =
+        decode_hex(signature).ok_or_else(|| SignatureError::Malformed(header.to_string()))?;
+
+    let key = secret.as_bytes();
+    match method.to_ascii_lowercase().as_str() {
+        "sha1" => verify_with::<Hmac<Sha1>>(key, body, &signature),
+        "sha256" => verify_with::<Hmac<Sha256>>(key, body, &signature),
+        "sha384" => verify_with::<Hmac<Sha384>>(key, body, &signature),
+        "sha512" => verify_with::<Hmac<Sha512>>(key, body, &signature),
+        _ => Err(SignatureError::UnsupportedMethod(method.to_string())),
+    }
+}
+
+fn verify_with<M: Mac + KeyInit>(
+    key: &[u8],
+    body: &[u8],
+    signature: &[u8],
+) -> Result<(), SignatureError> {
+    let mut mac = <M as KeyInit>::new_from_slice(key).map_err(|_| SignatureError::Mismatch)?;
+    mac.update(body);
+    // `verify_slice` compares in constant time
+    mac.verify_slice(signature)
+        .map_err(|_| SignatureError::Mismatch)
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            let pair = std::str::from_utf8(pair).ok()?;
+            u8::from_str_radix(pair, 16).ok()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 2202 and RFC 4231 test case 2
+    const KEY: &str = "Jefe";
+    const BODY: &[u8] = b"what do ya want for nothing?";
+    const SIGNATURES: [&str; 4] = [
+        "sha1=effcdf6ae5eb2fa2d27416d5f184df9c259a7c79",
+        "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+        "sha384=af45d2e376484031617f78d2b58a6b1b9c7ef464f5a01b47e42ec3736322445e\
+         8e2240ca5e69e2c78b3239ecfab21649",
+        "sha512=164b7a7bfcf819e2e395fbe73b56e0a387bd64222e831fd610270cd7ea250554\
+         9758bf75c05a994a6d034f65f8f0e6fdcaeab1a34d4a6b4b636e070a38bce737",
+    ];
+
+    #[test]
+    fn known_signatures() {
+        for signature in SIGNATURES {
+            assert!(verify(KEY, Some(signature), BODY).is_ok(), "{signature}");
+            // The method and hex digits are case insensitive
+            let upper = signature.to_ascii_uppercase();
+            assert!(verify(KEY, Some(&upper), BODY).is_ok(), "{upper}");
+        }
+    }
+
+    #[test]
+    fn wrong_signature() {
+        for signature in SIGNATURES {
+            assert!(matches!(
+                verify("secret", Some(signature), BODY),
+                Err(SignatureError::Mismatch)
+            ));
+            assert!(matches!(
+                verify(KEY, Some(signature), b"what do ya want for something?"),
+                Err(SignatureError::Mismatch)
+            ));
+        }
+
+        // A truncated signature does not match either
+        assert!(matches!(
+            verify(KEY, Some("sha1=effcdf6ae5eb2fa2"), BODY),
+            Err(SignatureError::Mismatch)
+        ));
+    }
+
+    #[test]
+    fn missing_header() {
+        assert!(matches!(
+            verify(KEY, None, BODY),
+            Err(SignatureError::Missing)
+        ));
+    }
+
+    #[test]
+    fn malformed_header() {
+        for header in [
+            "effcdf6ae5eb2fa2",
+            "sha1=effcdf6ae5eb2fa",
+            "sha1=zz",
+            "sha1=é0",
+        ] {
+            assert!(
+                matches!(
+                    verify(KEY, Some(header), BODY),
+                    Err(SignatureError::Malformed(_))
+                ),
+                "{header}"
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_method() {
+        assert!(matches!(
+            verify(KEY, Some("md5=750c783e6ab0b503eaa86e310a5db738"), BODY),
+            Err(SignatureError::UnsupportedMethod(method)) if method == "md5"
+        ));
+    }
+}
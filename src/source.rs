@@ -0,0 +1,238 @@
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    sync::Arc,
+};
+
+use crate::error::SourceError;
+use crate::headers::Headers;
+
+/// Range of IP addresses in CIDR notation, e.g. `66.249.64.0/19`.
+///
+/// A single address is a range of one, e.g. `127.0.0.1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    address: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.address, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = SourceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || SourceError::InvalidCidr(s.to_string());
+
+        let (address, prefix) = match s.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (s, None),
+        };
+        let address = address
+            .parse::<IpAddr>()
+            .map_err(|_| invalid())?
+            .to_canonical();
+        let max = if address.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse()
+                .ok()
+                .filter(|p| *p <= max)
+                .ok_or_else(invalid)?,
+            None => max,
+        };
+
+        Ok(Self { address, prefix })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.address, self.prefix)
+    }
+}
+
+/// Source of a request, given to the rules of a [`SourcePolicy`].
+#[derive(Debug)]
+pub struct Source<'a> {
+    /// Address of the peer of the connection.
+    pub peer: Option<SocketAddr>,
+    /// Address of the client, resolved through the trusted proxies, or
+    /// `None` if a proxy forwarded an unknown address.
+    pub client: Option<IpAddr>,
+    headers: &'a Headers,
+}
+
+impl Source<'_> {
+    /// Get a header of the request, the name is case insensitive.
+    ///
+    /// The values of a repeated header are joined with commas.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
+    }
+}
+
+type Rule = Arc<dyn Fn(&Source) -> bool + Send + Sync>;
+
+/// Decide which requests the listener trusts.
+///
+/// A request is trusted when its client address is in the allowlist, if
+/// any, every required header matches and every custom rule passes.
+#[derive(Clone, Default)]
+pub struct SourcePolicy {
+    allowlist: Vec<Cidr>,
+    trusted_proxies: Vec<Cidr>,
+    headers: Vec<(String, String)>,
+    rules: Vec<Rule>,
+}
+
+impl fmt::Debug for SourcePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SourcePolicy")
+            .field("allowlist", &self.allowlist)
+            .field("trusted_proxies", &self.trusted_proxies)
+            .field("headers", &self.headers)
+            .field("rules", &self.rules.len())
+            .finish()
+    }
+}
+
+impl SourcePolicy {
+    /// Trust every request.
+    pub fn any() -> Self {
+        Self::default()
+    }
+
+    /// Trust the requests from the Google hub, identified by their `From`
+    /// header. This is the default policy of a listener using the Google hub.
+    pub fn googlebot() -> Self {
+        Self::any().require_header("From", "googlebot(at)googlebot.com")
+    }
+
+    /// Trust the clients in `range`; once a range is allowed, the clients
+    /// outside of every allowed range are not trusted.
+    pub fn allow(mut self, range: Cidr) -> Self {
+        self.allowlist.push(range);
+        self
+    }
+
+    /// Resolve the client address of the requests from proxies in `range`
+    /// with their `Forwarded` or `X-Forwarded-For` header.
+    pub fn trusted_proxy(mut self, range: Cidr) -> Self {
+        self.trusted_proxies.push(range);
+        self
+    }
+
+    /// Only trust the requests whose header `name` is `value`.
+    pub fn require_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Only trust the requests for which `rule` returns `true`.
+    pub fn rule(mut self, rule: impl Fn(&Source) -> bool + Send + Sync + 'static) -> Self {
+        self.rules.push(Arc::new(rule));
+        self
+    }
+
+    /// Check a request from `peer` with `headers`.
+    ///
+    /// Returns why the request is not trusted.
+    pub(crate) fn check(&self, peer: Option<SocketAddr>, headers: &Headers) -> Result<(), String> {
+        let mut source = Source {
+            peer,
+            client: None,
+            headers,
+        };
+        source.client = peer.and_then(|peer| self.client(peer.ip(), &source));
+
+        if !self.allowlist.is_empty() {
+            match source.client {
+                Some(client) if self.allowlist.iter().any(|range| range.contains(client)) => (),
+                Some(client) => return Err(format!("client {client} not allowed")),
+                None => return Err("unknown client address".to_string()),
+            }
+        }
+
+        for (name, value) in &self.headers {
+            match source.header(name) {
+                Some(actual) if actual == value => (),
+                Some(actual) => return Err(format!("unexpected {name} header: {actual}")),
+                None => return Err(format!("missing {name} header")),
+            }
+        }
+
+        if !self.rules.iter().all(|rule| rule(&source)) {
+            return Err("rejected by a custom rule".to_string());
+        }
+
+        Ok(())
+    }
+
+    fn is_trusted_proxy(&self, ip: IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|range| range.contains(ip))
+    }
+
+    /// Walk the forwarded addresses back from `peer`, through the trusted
+    /// proxies, to the client.
+    fn client(&self, peer: IpAddr, source: &Source) -> Option<IpAddr> {
+        if !self.is_trusted_proxy(peer) {
+            return Some(peer);
+        }
+
+        // The closest proxy appends its client last
+        let hops = match (source.header("Forwarded"), source.header("X-Forwarded-For")) {
+            (Some(forwarded), _) => forwarded.split(',').map(forwarded_for).collect(),
+            (None, Some(forwarded_for)) => forwarded_for
+                .split(',')
+                .map(|hop| hop.trim().parse().ok())
+                .collect(),
+            (None, None) => vec![],
+        };
+
+        let mut client = peer;
+        for hop in hops.into_iter().rev() {
+            client = hop?;
+            if !self.is_trusted_proxy(client) {
+                break;
+            }
+        }
+        Some(client)
+    }
+}
+
+/// Address of the `for` parameter of a `Forwarded` element, e.g.
+/// `for=192.0.2.60;proto=http` or `for="[2001:db8::1]:4711"`.
+fn forwarded_for(element: &str) -> Option<IpAddr> {
+    let node = element.split(';').find_map(|pair| {
+        let (name, value) = pair.trim().split_once('=')?;
+        name.eq_ignore_ascii_case("for").then_some(value)
+    })?;
+    let node = node.trim_matches('"');
+
+    if let Some(node) = node.strip_prefix('[') {
+        return node.split(']').next()?.parse().ok();
+    }
+    node.parse()
+        .ok()
+        .or_else(|| node.rsplit_once(':')?.0.parse().ok())
+}
@@ -0,0 +1,59 @@
+use std::{ffi::OsString, fs, path::Path};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::error::StateFileError;
+
+/// Load the state saved to the toml file at `path`, if it exists.
+pub(crate) fn load<T: DeserializeOwned>(path: &Path) -> Result<Option<T>, StateFileError> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(toml::from_str(&fs::read_to_string(path)?)?))
+}
+
+/// Save `state` to the toml file at `path`.
+///
+/// The state is written to `<path>.tmp` then renamed over `path`, so the
+/// file is never half written.
+pub(crate) fn save<T: Serialize>(path: &Path, state: &T) -> Result<(), StateFileError> {
+    let mut tmp = OsString::from(path);
+    tmp.push(".tmp");
+
+    fs::write(&tmp, toml::to_string(state)?)?;
+    fs::rename(tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn save_and_load() {
+        let dir = std::env::temp_dir().join(format!("brzthook-{:016x}", fastrand::u64(..)));
+        fs::create_dir(&dir).unwrap();
+        let toml_path = dir.join("state.toml");
+        let json_path = dir.join("state.json");
+
+        assert_eq!(load::<HashMap<String, u32>>(&toml_path).unwrap(), None);
+
+        let state = HashMap::from([("a".to_string(), 1)]);
+        save(&toml_path, &state).unwrap();
+        save(&json_path, &state).unwrap();
+        assert_eq!(load(&toml_path).unwrap(), Some(state.clone()));
+        assert_eq!(load(&json_path).unwrap(), Some(state));
+
+        // The temporary files are renamed, none is left behind
+        let mut files: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        files.sort();
+        assert_eq!(files, ["state.json", "state.toml"]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,186 @@
+use std::{path::PathBuf, sync::Mutex, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::error::StorageError;
+use crate::registry::{Registration, SubscriptionState};
+use crate::state_file;
+use crate::subscription::Lease;
+
+/// Storage of the subscription state, so a restarted listener knows its
+/// subscriptions and when to renew them.
+///
+/// The registry saves every subscription on each change, and loads them
+/// back when the listener is built.
+pub trait Storage: Send + Sync + 'static {
+    /// Load the saved subscriptions, none if nothing was saved yet.
+    fn load(&self) -> Result<Vec<Registration>, StorageError>;
+
+    /// Replace the saved subscriptions with `registrations`.
+    fn save(&self, registrations: &[Registration]) -> Result<(), StorageError>;
+}
+
+/// Storage keeping the subscriptions in memory, e.g. to share them between
+/// listeners built one after the other in the same process.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    registrations: Mutex<Vec<Registration>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn load(&self) -> Result<Vec<Registration>, StorageError> {
+        Ok(self.registrations.lock().unwrap().clone())
+    }
+
+    fn save(&self, registrations: &[Registration]) -> Result<(), StorageError> {
+        *self.registrations.lock().unwrap() = registrations.to_vec();
+        Ok(())
+    }
+}
+
+/// Storage saving the subscriptions to a toml file.
+#[derive(Debug, Clone)]
+pub struct FileStorage {
+    path: PathBuf,
+}
+
+impl FileStorage {
+    /// Save to `path`, nothing is loaded if the file does not exist yet.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl Storage for FileStorage {
+    fn load(&self) -> Result<Vec<Registration>, StorageError> {
+        let file: File = state_file::load(&self.path)?.unwrap_or_default();
+        file.registrations
+            .into_iter()
+            .map(Stored::into_registration)
+            .collect()
+    }
+
+    fn save(&self, registrations: &[Registration]) -> Result<(), StorageError> {
+        let file = File {
+            registrations: registrations.iter().map(Stored::from).collect(),
+        };
+        Ok(state_file::save(&self.path, &file)?)
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct File {
+    registrations: Vec<Stored>,
+}
+
+/// Registration as saved to the file, with unix timestamps and durations in
+/// seconds.
+#[derive(Debug, Serialize, Deserialize)]
+struct Stored {
+    topic: String,
+    /// `pending`, `active`, `unsubscribed`, `denied` or `redirected`.
+    state: String,
+    /// Reason of a denial.
+    reason: Option<String>,
+    /// Status and location of a redirection.
+    status: Option<u16>,
+    location: Option<String>,
+    /// Mode of the request waiting for its verification.
+    pending: Option<String>,
+    callback_id: Option<String>,
+    requested: Option<u64>,
+    granted: Option<u64>,
+    expires_at: Option<i64>,
+    renew_at: Option<i64>,
+}
+
+impl From<&Registration> for Stored {
+    fn from(registration: &Registration) -> Self {
+        let (mut reason, mut status, mut location) = (None, None, None);
+        let state = match &registration.state {
+            SubscriptionState::Pending => "pending",
+            SubscriptionState::Active { .. } => "active",
+            SubscriptionState::Unsubscribed => "unsubscribed",
+            SubscriptionState::Denied { reason: denial } => {
+                reason = Some(denial.clone());
+                "denied"
+            }
+            SubscriptionState::Redirected {
+                status: redirection,
+                location: to,
+            } => {
+                status = Some(*redirection);
+                location = to.clone();
+                "redirected"
+            }
+        };
+        let lease = &registration.lease;
+
+        Self {
+            topic: registration.topic.clone(),
+            state: state.to_string(),
+            reason,
+            status,
+            location,
+            pending: registration.pending.map(|mode| mode.to_string()),
+            callback_id: registration.callback_id.clone(),
+            requested: lease.requested.map(|lease| lease.as_secs()),
+            granted: lease.granted.map(|lease| lease.as_secs()),
+            expires_at: lease.expires_at.map(OffsetDateTime::unix_timestamp),
+            renew_at: lease.renew_at.map(OffsetDateTime::unix_timestamp),
+        }
+    }
+}
+
+impl Stored {
+    fn into_registration(self) -> Result<Registration, StorageError> {
+        let timestamp = |timestamp: Option<i64>| {
+            timestamp
+                .map(OffsetDateTime::from_unix_timestamp)
+                .transpose()
+                .map_err(|_| StorageError::Invalid(format!("timestamp for {}", self.topic)))
+        };
+        let expires_at = timestamp(self.expires_at)?;
+        let renew_at = timestamp(self.renew_at)?;
+
+        let state = match self.state.as_str() {
+            "pending" => SubscriptionState::Pending,
+            "active" => SubscriptionState::Active { expires_at },
+            "unsubscribed" => SubscriptionState::Unsubscribed,
+            "denied" => SubscriptionState::Denied {
+                reason: self.reason.unwrap_or_default(),
+            },
+            "redirected" => SubscriptionState::Redirected {
+                status: self.status.unwrap_or_default(),
+                location: self.location,
+            },
+            state => return Err(StorageError::Invalid(format!("state {state}"))),
+        };
+        let pending = self
+            .pending
+            .map(|mode| mode.parse())
+            .transpose()
+            .map_err(|_| StorageError::Invalid(format!("pending mode for {}", self.topic)))?;
+
+        Ok(Registration {
+            lease: Lease {
+                topic: self.topic.clone(),
+                requested: self.requested.map(Duration::from_secs),
+                granted: self.granted.map(Duration::from_secs),
+                expires_at,
+                renew_at,
+            },
+            topic: self.topic,
+            state,
+            pending,
+            callback_id: self.callback_id,
+        })
+    }
+}
@@ -0,0 +1,206 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use time::OffsetDateTime;
+use tracing::info;
+
+use crate::error::Error;
+use crate::hub::{Hub, SubscribeOutcome};
+use crate::registry::{Failure, Registry};
+use crate::{Mode, RENEWAL_RETRY};
+
+/// Longest lease accepted from a hub, the leases are usually days long.
+pub(crate) const MAX_LEASE: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+/// Lease of a subscription to a topic.
+#[derive(Debug, Clone)]
+pub struct Lease {
+    pub topic: String,
+    /// Lease duration sent as `hub.lease_seconds` with the subscription request.
+    pub requested: Option<Duration>,
+    /// Lease duration granted by the hub in the verification request.
+    pub granted: Option<Duration>,
+    /// When the lease expires, if the hub granted one.
+    pub expires_at: Option<OffsetDateTime>,
+    /// When the subscription will be renewed.
+    pub renew_at: Option<OffsetDateTime>,
+}
+
+impl Lease {
+    pub(crate) fn new(topic: &str) -> Self {
+        Self {
+            topic: topic.to_string(),
+            requested: None,
+            granted: None,
+            expires_at: None,
+            renew_at: None,
+        }
+    }
+}
+
+/// Verification of intent sent by the hub.
+#[derive(Debug, Clone)]
+pub struct Verification {
+    pub topic: String,
+    pub mode: Mode,
+    /// Lease granted by the hub, only for subscriptions.
+    pub lease: Option<Duration>,
+}
+
+/// User hook approving or denying a verification of intent.
+pub(crate) type VerifyHook = Arc<dyn Fn(&Verification) -> bool + Send + Sync>;
+
+/// Sends the subscription requests to the hub and tracks their state.
+#[derive(Debug, Clone)]
+pub(crate) struct Subscriber {
+    pub(crate) hub: Arc<Hub>,
+    pub(crate) callbacks: Arc<Callbacks>,
+    pub(crate) registry: Arc<Registry>,
+}
+
+impl Subscriber {
+    /// See [`crate::HookListener::subscribe_topic`].
+    pub(crate) fn request(
+        &self,
+        topic: &str,
+        mode: Mode,
+        lease: Option<Duration>,
+    ) -> Result<SubscribeOutcome, Error> {
+        info!("Initiating {mode} request for topic: {topic}");
+
+        // The request is recorded first as the hub may verify it before replying
+        let callback = self.callbacks.url_for(topic);
+        self.registry
+            .requested(topic, mode, lease, self.callbacks.id(topic));
+
+        let outcome = self.hub.send(&callback, topic, mode, lease);
+        let failure = match &outcome {
+            Ok(SubscribeOutcome::Accepted) => None,
+            Ok(rejected @ SubscribeOutcome::Rejected { retry_after, .. })
+                if rejected.is_transient() =>
+            {
+                let now = OffsetDateTime::now_utc();
+                let at = match retry_after {
+                    Some(retry_after) => retry_after.after(now),
+                    None => now + RENEWAL_RETRY,
+                };
+                Some(Failure::Retry(at))
+            }
+            Ok(SubscribeOutcome::Redirect { status, location }) => Some(Failure::Redirected {
+                status: *status,
+                location: location.clone(),
+            }),
+            Ok(outcome) => Some(Failure::Denied(outcome.to_string())),
+            Err(_) => Some(Failure::Unreachable),
+        };
+        if let Some(failure) = failure {
+            self.registry.failed(topic, mode, failure);
+        }
+
+        outcome
+    }
+}
+
+/// Subscription a notification was delivered for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Subscription {
+    pub topic: String,
+    /// Callback url given to the hub for this topic.
+    pub callback: String,
+}
+
+/// Callback urls given to the hub.
+///
+/// With per-topic callbacks, each topic gets its own path made of the
+/// callback url path and an opaque id, e.g. `/hook/9f86d081884c7d65`, so
+/// deliveries can be attributed to their subscription.
+#[derive(Debug)]
+pub(crate) struct Callbacks {
+    /// Callback url set on the builder.
+    base: String,
+    /// Path of the callback url.
+    base_path: String,
+    per_topic: bool,
+    /// Topic url by callback id.
+    topics: Mutex<HashMap<String, String>>,
+}
+
+impl Callbacks {
+    pub(crate) fn new(base: String, base_path: String, per_topic: bool) -> Self {
+        Self {
+            base,
+            base_path,
+            per_topic,
+            topics: Mutex::default(),
+        }
+    }
+
+    /// Get the callback url for `topic`, creating its id if needed.
+    pub(crate) fn url_for(&self, topic: &str) -> String {
+        if !self.per_topic {
+            return self.base.clone();
+        }
+
+        let mut topics = self.topics.lock().unwrap();
+        let id = match topics.iter().find(|(_, t)| *t == topic) {
+            Some((id, _)) => id.clone(),
+            None => {
+                let id = format!("{:016x}", fastrand::u64(..));
+                topics.insert(id.clone(), topic.to_string());
+                id
+            }
+        };
+
+        self.with_id(&id)
+    }
+
+    /// Id of the callback of `topic`, with per-topic callbacks.
+    pub(crate) fn id(&self, topic: &str) -> Option<String> {
+        let topics = self.topics.lock().unwrap();
+        topics
+            .iter()
+            .find(|(_, t)| *t == topic)
+            .map(|(id, _)| id.clone())
+    }
+
+    /// Restore the callback `id` of `topic` given to the hub before a
+    /// restart.
+    pub(crate) fn restore(&self, id: &str, topic: &str) {
+        if self.per_topic {
+            self.topics
+                .lock()
+                .unwrap()
+                .insert(id.to_string(), topic.to_string());
+        }
+    }
+
+    /// Find the subscription whose callback is `path`.
+    pub(crate) fn find(&self, path: &str) -> Option<Subscription> {
+        let id = path
+            .strip_prefix(self.base_path.trim_end_matches('/'))?
+            .strip_prefix('/')?;
+        let topics = self.topics.lock().unwrap();
+        let topic = topics.get(id)?;
+
+        Some(Subscription {
+            topic: topic.clone(),
+            callback: self.with_id(id),
+        })
+    }
+
+    /// Forget the callback of `topic` once unsubscribed.
+    pub(crate) fn remove(&self, topic: &str) {
+        self.topics.lock().unwrap().retain(|_, t| t != topic);
+    }
+
+    /// Insert `/{id}` at the end of the base url path, before the query.
+    fn with_id(&self, id: &str) -> String {
+        match self.base.split_once('?') {
+            Some((url, query)) => format!("{}/{id}?{query}", url.trim_end_matches('/')),
+            None => format!("{}/{id}", self.base.trim_end_matches('/')),
+        }
+    }
+}